@@ -0,0 +1,20 @@
+// Hex input parsers: seeds and keys typed by the user and pasted UDS frames
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use seed2cmac::uds;
+use seed2cmac::util::{bytes_to_hex_string, hex_string_to_bytes};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    if let Ok(bytes) = hex_string_to_bytes(text) {
+        assert_eq!(bytes_to_hex_string(&bytes), text.to_ascii_lowercase());
+    }
+    if let Ok(frame) = uds::parse_hex_frame(text) {
+        let _ = uds::parse_seed_response(&frame);
+    }
+});
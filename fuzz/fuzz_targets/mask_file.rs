@@ -0,0 +1,24 @@
+// Mask file parser, and calculation with every entry it accepts
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use seed2cmac::{MaskTable, SeedKeyCalculator};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(table) = MaskTable::parse(text) else {
+        return;
+    };
+
+    for entry in table.entries() {
+        let Ok(calculator) = SeedKeyCalculator::from_table(&table, &entry.ecu, entry.level) else {
+            continue;
+        };
+        let seed = vec![0x5A; calculator.seed_len()];
+        let key_len = calculator.key_lengths().first().copied().unwrap_or(16);
+        let _ = calculator.calculate(&seed, &vec![0xA5; key_len]);
+    }
+});
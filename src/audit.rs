@@ -0,0 +1,318 @@
+//! Append-only audit log of key computations.
+//!
+//! Every entry is a JSON line recording when which OS user calculated a key
+//! response for which ECU and security level, with SHA-256 hashes of the seed
+//! and the response but never the key. Each entry carries the hash of the entry
+//! before it, so editing, removing or reordering entries breaks the chain
+//! checked by [`AuditLog::verify`].
+
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::mask::{config_dir, SecurityLevel};
+
+/// File name of the audit log in the config directory
+pub const AUDIT_LOG_FILE: &str = "audit.jsonl";
+
+/// `prev` of the first entry
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// A key computation to record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// Front end that calculated the key, e.g. `gui`, `headless` or `batch`, or
+    /// checked a recorded one, `gui-verify` or `headless-verify`
+    pub source: String,
+    pub ecu: String,
+    pub level: SecurityLevel,
+    pub seed_sha256: String,
+    pub result_sha256: String,
+}
+
+impl AuditEvent {
+    /// Describes the calculation of `response` from `seed`, keeping only their hashes
+    pub fn new(source: &str, ecu: &str, level: SecurityLevel, seed: &[u8], response: &[u8]) -> Self {
+        Self {
+            source: source.to_string(),
+            ecu: ecu.to_string(),
+            level,
+            seed_sha256: sha256_hex(seed),
+            result_sha256: sha256_hex(response),
+        }
+    }
+}
+
+/// Hashed content of an entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the log, starting at 1
+    pub seq: u64,
+    /// UTC time of the calculation, e.g. `2024-05-01T08:30:00Z`
+    pub timestamp: String,
+    pub user: String,
+    pub source: String,
+    pub ecu: String,
+    pub level: String,
+    pub seed_sha256: String,
+    pub result_sha256: String,
+    /// Hash of the previous entry
+    pub prev: String,
+}
+
+impl AuditRecord {
+    /// Returns the SHA-256 of the JSON encoded record as hex
+    fn hash(&self) -> Result<String, Box<dyn Error>> {
+        Ok(sha256_hex(&serde_json::to_vec(self)?))
+    }
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(flatten)]
+    pub record: AuditRecord,
+    pub hash: String,
+}
+
+/// Audit log file that entries are only ever appended to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns `audit.jsonl` in the seed2cmac config directory
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(AUDIT_LOG_FILE))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends an entry for the event
+    pub fn record(&self, event: &AuditEvent) -> Result<(), Box<dyn Error>> {
+        self.record_all(std::slice::from_ref(event))
+    }
+
+    /// Appends an entry for each event, chained to the last entry of the log,
+    /// holding an exclusive lock on the file meanwhile
+    pub fn record_all(&self, events: &[AuditEvent]) -> Result<(), Box<dyn Error>> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        // Writers in other threads and processes wait until the entries are chained and written
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+        file.lock().map_err(|e| format!("{}: {}", self.path.display(), e))?;
+
+        let last = last_line(&mut file)?
+            .map(|line| serde_json::from_str::<AuditEntry>(&line))
+            .transpose()
+            .map_err(|e| format!("Audit log last entry: {}", e))?;
+        let (mut seq, mut prev) = match last {
+            Some(last) => (last.record.seq, last.hash),
+            None => (0, GENESIS.to_string()),
+        };
+        let timestamp = utc_timestamp(SystemTime::now());
+        let user = os_user();
+
+        let mut lines = String::new();
+        for event in events {
+            seq += 1;
+            let record = AuditRecord {
+                seq,
+                timestamp: timestamp.clone(),
+                user: user.clone(),
+                source: event.source.clone(),
+                ecu: event.ecu.clone(),
+                level: event.level.to_string(),
+                seed_sha256: event.seed_sha256.clone(),
+                result_sha256: event.result_sha256.clone(),
+                prev,
+            };
+            let entry = AuditEntry { hash: record.hash()?, record };
+            lines.push_str(&serde_json::to_string(&entry)?);
+            lines.push('\n');
+            prev = entry.hash;
+        }
+
+        file.write_all(lines.as_bytes())?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Reads the entries of the log, empty if it does not exist yet
+    pub fn entries(&self) -> Result<Vec<AuditEntry>, Box<dyn Error>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let contents = fs::read_to_string(&self.path).map_err(|e| format!("{}: {}", self.path.display(), e))?;
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| serde_json::from_str(line).map_err(|e| format!("Audit log line {}: {}", index + 1, e).into()))
+            .collect()
+    }
+
+    /// Checks the hash chain of the log and returns the number of entries
+    pub fn verify(&self) -> Result<usize, Box<dyn Error>> {
+        let contents = fs::read_to_string(&self.path).map_err(|e| format!("{}: {}", self.path.display(), e))?;
+        verify_chain(&contents)
+    }
+}
+
+/// Checks the hash chain of the contents of an audit log and returns the number of entries
+pub fn verify_chain(contents: &str) -> Result<usize, Box<dyn Error>> {
+    let mut prev = GENESIS.to_string();
+    let mut count = 0;
+    for (index, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let line_number = index + 1;
+        let entry: AuditEntry =
+            serde_json::from_str(line).map_err(|e| format!("Audit log line {}: {}", line_number, e))?;
+
+        count += 1;
+        if entry.record.seq != count as u64 {
+            return Err(format!("Audit log line {}: expected entry {}, found {}", line_number, count, entry.record.seq).into());
+        }
+        if entry.record.prev != prev {
+            return Err(format!("Audit log line {}: chain broken, previous entry hash does not match", line_number).into());
+        }
+        if entry.record.hash()? != entry.hash {
+            return Err(format!("Audit log line {}: entry hash does not match its content", line_number).into());
+        }
+        prev = entry.hash;
+    }
+    Ok(count)
+}
+
+/// Returns the last non-empty line of the file, reading it backwards from the end
+fn last_line(file: &mut File) -> Result<Option<String>, Box<dyn Error>> {
+    const CHUNK: u64 = 4096;
+    let mut end = file.seek(SeekFrom::End(0))?;
+    let mut tail = Vec::new();
+    loop {
+        let start = end.saturating_sub(CHUNK);
+        let mut chunk = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+        end = start;
+
+        let content = tail.trim_ascii_end();
+        if let Some(newline) = content.iter().rposition(|byte| *byte == b'\n') {
+            return Ok(Some(String::from_utf8(content[newline + 1..].to_vec())?));
+        }
+        if start == 0 {
+            return Ok((!content.is_empty()).then(|| String::from_utf8(content.to_vec())).transpose()?);
+        }
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Returns the name of the OS account running the process.
+///
+/// The name is looked up from the real user id, which unlike `USER` cannot be
+/// changed by the caller; the environment is only used if the lookup fails.
+fn os_user() -> String {
+    account_name()
+        .or_else(|| std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok())
+        .filter(|user| !user.is_empty())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+#[cfg(unix)]
+fn account_name() -> Option<String> {
+    use std::ffi::CStr;
+
+    let uid = unsafe { libc::getuid() };
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let status = unsafe { libc::getpwuid_r(uid, &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+        if status == libc::ERANGE && buffer.len() < 1024 * 1024 {
+            buffer.resize(buffer.len() * 2, 0);
+            continue;
+        }
+        break;
+    }
+
+    if result.is_null() || passwd.pw_name.is_null() {
+        return None;
+    }
+    // The name points into `buffer`, which is still alive
+    let name = unsafe { CStr::from_ptr(passwd.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(windows)]
+fn account_name() -> Option<String> {
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn GetUserNameW(buffer: *mut u16, size: *mut u32) -> i32;
+    }
+    // UNLEN + 1
+    let mut buffer = [0u16; 257];
+    let mut size = buffer.len() as u32;
+    if unsafe { GetUserNameW(buffer.as_mut_ptr(), &mut size) } == 0 {
+        return None;
+    }
+    // The size includes the terminating null
+    Some(String::from_utf16_lossy(&buffer[..size.saturating_sub(1) as usize]))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn account_name() -> Option<String> {
+    None
+}
+
+/// Formats the time as RFC 3339 in UTC with second precision
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant's algorithm
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
+}
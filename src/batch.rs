@@ -0,0 +1,256 @@
+//! Batch key calculation for `(ecu, level, seed)` rows from CSV or JSON Lines files.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{AuditEvent, AuditLog};
+use crate::calculator::SeedKeyCalculator;
+use crate::keystore::{resolve_key, KeyStore};
+use crate::mask::{MaskTable, SecurityLevel};
+use crate::util::{parse_hex_input, OutputFormat, SecretBytes, SecretString};
+
+/// File format of batch input and output, chosen by file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchFormat {
+    /// Comma separated values with an `ecu,level,seed,key` header
+    Csv,
+    /// One JSON object per line, e.g. `{"ecu": "BMS", "level": 1, "seed": "…"}`
+    JsonLines,
+}
+
+impl BatchFormat {
+    /// Returns CSV for `.csv` files, JSON Lines for `.jsonl` and `.ndjson` files and
+    /// `None` for any other file, including `.json` which is not one object per line
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("csv") => Some(BatchFormat::Csv),
+            Some("jsonl" | "ndjson") => Some(BatchFormat::JsonLines),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            BatchFormat::Csv => "csv",
+            BatchFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+/// Security level as written in the file, a number in JSON or text such as `0x11`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum LevelField {
+    Number(u64),
+    Text(String),
+}
+
+/// One input row, `key` being the hex key, an `@alias` of the key store or empty
+/// to use the default key
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchRow {
+    /// Line number in the input file
+    pub line: usize,
+    pub ecu: String,
+    pub level: String,
+    pub seed: String,
+    pub key: Option<SecretString>,
+}
+
+/// A row, or the line number and the reason it could not be read
+pub type RowResult = Result<BatchRow, (usize, String)>;
+
+#[derive(Debug, Deserialize)]
+struct RawRow {
+    ecu: String,
+    level: LevelField,
+    seed: String,
+    #[serde(default)]
+    key: Option<SecretString>,
+}
+
+impl RawRow {
+    fn into_row(self, line: usize) -> BatchRow {
+        let level = match self.level {
+            LevelField::Number(level) => level.to_string(),
+            LevelField::Text(level) => level,
+        };
+        let key = self.key.map(|key| SecretString::from(key.trim())).filter(|key| !key.is_empty());
+        BatchRow { line, ecu: self.ecu.trim().to_string(), level: level.trim().to_string(), seed: self.seed.trim().to_string(), key }
+    }
+}
+
+/// Output row with the calculated response or the reason the row failed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BatchResult {
+    pub line: usize,
+    pub ecu: String,
+    pub level: String,
+    pub seed: String,
+    pub response: String,
+    pub error: String,
+}
+
+impl BatchResult {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_empty()
+    }
+}
+
+/// Number of rows processed and failed by a batch run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub rows: usize,
+    pub failed: usize,
+}
+
+/// Reads the rows of the input, keeping unreadable rows as errors with their line number
+pub fn read_rows(reader: impl Read, format: BatchFormat) -> Result<Vec<RowResult>, Box<dyn Error>> {
+    match format {
+        BatchFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(reader);
+            let headers = reader.headers()?.clone();
+            Ok(reader
+                .records()
+                .map(|record| {
+                    let record = record.map_err(|e| (e.position().map_or(0, |p| p.line() as usize), e.to_string()))?;
+                    let line = record.position().map_or(0, |p| p.line() as usize);
+                    record
+                        .deserialize::<RawRow>(Some(&headers))
+                        .map(|row| row.into_row(line))
+                        .map_err(|e| (line, e.to_string()))
+                })
+                .collect())
+        }
+        BatchFormat::JsonLines => Ok(BufReader::new(reader)
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line))
+            .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .map(|(number, line)| {
+                let line = line.map_err(|e| (number, e.to_string()))?;
+                serde_json::from_str::<RawRow>(&line)
+                    .map(|row| row.into_row(number))
+                    .map_err(|e| (number, e.to_string()))
+            })
+            .collect()),
+    }
+}
+
+/// Calculates the key response of one row, using `default_key` if the row has no
+/// key and resolving `@alias` keys from the key store
+pub fn calculate_row(
+    table: &MaskTable,
+    row: &BatchRow,
+    default_key: Option<&str>,
+    keys: Option<&KeyStore>,
+) -> Result<SecretBytes, Box<dyn Error>> {
+    let ecu = table.ecu(&row.ecu).ok_or_else(|| format!("Unknown ECU type: {}", row.ecu))?;
+    let level = row.level.parse::<SecurityLevel>()?;
+    let calculator = SeedKeyCalculator::from_table(table, &ecu, level)?;
+
+    let key = row.key.as_deref().or(default_key).ok_or("No key given for the row and no default key")?;
+    let key = resolve_key(key, keys)?;
+    let seed = parse_hex_input(&row.seed)
+        .ok()
+        .filter(|seed| seed.len() == calculator.seed_len())
+        .ok_or_else(|| format!("Invalid seed: must be a {} character hex string", calculator.seed_len() * 2))?;
+
+    calculator.calculate(&seed, &key)
+}
+
+/// Calculates every row, a failing row only failing its own result
+pub fn process(table: &MaskTable, rows: Vec<RowResult>, default_key: Option<&str>, keys: Option<&KeyStore>) -> Vec<BatchResult> {
+    rows.into_iter()
+        .map(|row| match row {
+            Ok(row) => {
+                let (response, error) = match calculate_row(table, &row, default_key, keys) {
+                    Ok(response) => (hex::encode(response), String::new()),
+                    Err(e) => (String::new(), e.to_string()),
+                };
+                BatchResult { line: row.line, ecu: row.ecu, level: row.level, seed: row.seed, response, error }
+            }
+            Err((line, error)) => BatchResult { line, error, ..Default::default() },
+        })
+        .collect()
+}
+
+/// Writes the results with `line,ecu,level,seed,response,error` columns or fields
+pub fn write_results(writer: impl Write, format: BatchFormat, results: &[BatchResult]) -> Result<(), Box<dyn Error>> {
+    match format {
+        BatchFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for result in results {
+                writer.serialize(result)?;
+            }
+            writer.flush()?;
+        }
+        BatchFormat::JsonLines => {
+            let mut writer = BufWriter::new(writer);
+            for result in results {
+                serde_json::to_writer(&mut writer, result)?;
+                writeln!(writer)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the default output path next to the input, e.g. `eol.keys.csv` for `eol.csv`
+pub fn output_path(input: &Path) -> PathBuf {
+    let stem = input.file_stem().and_then(|s| s.to_str()).unwrap_or("batch");
+    input.with_file_name(format!("{}.keys.{}", stem, BatchFormat::from_path(input).unwrap_or(BatchFormat::Csv).extension()))
+}
+
+/// Returns the audit events of the calculated rows
+pub fn audit_events(results: &[BatchResult]) -> Vec<AuditEvent> {
+    results
+        .iter()
+        .filter(|result| result.is_ok())
+        .filter_map(|result| {
+            let level = result.level.parse::<SecurityLevel>().ok()?;
+            let seed = parse_hex_input(&result.seed).ok()?;
+            let response = parse_hex_input(&result.response).ok()?;
+            Some(AuditEvent::new("batch", &result.ecu, level, &seed, &response))
+        })
+        .collect()
+}
+
+/// Rewrites the hex responses of the calculated rows in the output format
+pub fn format_responses(results: &mut [BatchResult], format: OutputFormat) {
+    for result in results.iter_mut().filter(|result| result.is_ok()) {
+        if let (Ok(level), Ok(response)) = (result.level.parse::<SecurityLevel>(), parse_hex_input(&result.response)) {
+            result.response = format.format(&response, level).to_string();
+        }
+    }
+}
+
+/// Calculates the keys of every row of the input file and writes them to the
+/// output file in the same file format with responses in `response_format`,
+/// recording the calculated rows in the audit log first if one is given
+pub fn run(
+    table: &MaskTable,
+    input: &Path,
+    output: &Path,
+    default_key: Option<&str>,
+    keys: Option<&KeyStore>,
+    audit: Option<&AuditLog>,
+    response_format: OutputFormat,
+) -> Result<BatchSummary, Box<dyn Error>> {
+    let format = BatchFormat::from_path(input)
+        .ok_or_else(|| format!("{}: unsupported batch file, expected a .csv, .jsonl or .ndjson file", input.display()))?;
+    let rows = read_rows(File::open(input).map_err(|e| format!("{}: {}", input.display(), e))?, format)?;
+    let mut results = process(table, rows, default_key, keys);
+    if let Some(audit) = audit {
+        audit.record_all(&audit_events(&results)).map_err(|e| format!("Audit log failed: {}", e))?;
+    }
+    format_responses(&mut results, response_format);
+    write_results(File::create(output).map_err(|e| format!("{}: {}", output.display(), e))?, format, &results)?;
+
+    Ok(BatchSummary { rows: results.len(), failed: results.iter().filter(|r| !r.is_ok()).count() })
+}
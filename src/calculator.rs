@@ -0,0 +1,219 @@
+use std::error::Error;
+
+use crate::crypto::{self, CmacCipher, SeedKeyAlgorithm};
+use crate::mask::{EcuType, MaskEntry, MaskTable, SecurityLevel};
+use crate::util::{bytes_to_secret_hex, parse_hex_input, xor_bytes, HexInputError, SecretBytes, SecretString};
+
+/// Derives the security access key for one ECU and security level.
+///
+/// The seed is XORed with the mask configured for the ECU/level pair and the
+/// result is passed with the user supplied key to the algorithm named by the
+/// entry, AES-CMAC unless configured otherwise. The algorithm output is
+/// truncated to the response length configured for the entry.
+#[derive(Debug, Clone)]
+pub struct SeedKeyCalculator {
+    entry: MaskEntry,
+    algorithm: &'static dyn SeedKeyAlgorithm,
+}
+
+impl SeedKeyCalculator {
+    /// Creates a calculator using the mask of the built-in table
+    pub fn new(ecu: &EcuType, level: SecurityLevel) -> Result<Self, Box<dyn Error>> {
+        Self::from_table(&MaskTable::builtin(), ecu, level)
+    }
+
+    /// Creates a calculator using the mask of the given table
+    pub fn from_table(table: &MaskTable, ecu: &EcuType, level: SecurityLevel) -> Result<Self, Box<dyn Error>> {
+        let entry = table
+            .get(ecu, level)
+            .ok_or_else(|| format!("找不到ECU: {} 与安全等级: {} 对应的掩码", ecu, level))?;
+        let algorithm = crypto::algorithm(&entry.algorithm)
+            .ok_or_else(|| format!("ECU: {} 的算法 {} 不受支持", ecu, entry.algorithm))?;
+
+        Ok(Self { entry: entry.clone(), algorithm })
+    }
+
+    /// Replaces the algorithm named by the mask entry, e.g. with a custom implementation
+    pub fn with_algorithm(mut self, algorithm: &'static dyn SeedKeyAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn ecu(&self) -> &EcuType {
+        &self.entry.ecu
+    }
+
+    pub fn level(&self) -> SecurityLevel {
+        self.entry.level
+    }
+
+    /// Returns the mask configured for the ECU and security level
+    pub fn mask(&self) -> &[u8] {
+        &self.entry.mask
+    }
+
+    /// Returns the expected seed length in bytes
+    pub fn seed_len(&self) -> usize {
+        self.entry.seed_len()
+    }
+
+    /// Returns the configured AES variant, `None` if it is chosen by key length
+    pub fn cipher(&self) -> Option<CmacCipher> {
+        self.entry.cipher
+    }
+
+    /// Returns the seed-to-key algorithm
+    pub fn algorithm(&self) -> &'static dyn SeedKeyAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns the accepted key lengths in bytes, empty if any non-empty key is accepted
+    pub fn key_lengths(&self) -> Vec<usize> {
+        self.algorithm.key_lengths(self.entry.cipher)
+    }
+
+    /// Checks that the key has a length accepted by the algorithm
+    pub fn check_key(&self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.algorithm.check_key(key, self.entry.cipher)
+    }
+
+    /// Returns the key response length in bytes
+    pub fn response_len(&self) -> usize {
+        self.entry.response_len
+    }
+
+    /// Calculates the key response from raw seed and key bytes
+    ///
+    /// # Arguments
+    /// * `seed` - The seed received from the ECU
+    /// * `key` - The secret key of the algorithm
+    ///
+    /// # Returns
+    /// * `Result<SecretBytes, Box<dyn Error>>` - The calculated key response bytes or an error
+    pub fn calculate(&self, seed: &[u8], key: &[u8]) -> Result<SecretBytes, Box<dyn Error>> {
+        let mask_value = self.masked_seed(seed)?;
+
+        let name = self.algorithm.name().to_ascii_uppercase();
+        self.check_key(key)?;
+        let mut response = self
+            .algorithm
+            .calculate(&mask_value, key, self.entry.cipher)
+            .map_err(|e| format!("{}计算失败: {}", name, e))?;
+        if response.len() < self.entry.response_len {
+            return Err(format!("{}计算失败: 输出只有{}个字节", name, response.len()).into());
+        }
+        response.truncate(self.entry.response_len);
+
+        Ok(response)
+    }
+
+    /// Checks a recorded key response against the expected one in constant time
+    ///
+    /// # Returns
+    /// * `Result<bool, Box<dyn Error>>` - Whether the response matches, or an error if the seed or key is invalid
+    pub fn verify(&self, seed: &[u8], key: &[u8], response: &[u8]) -> Result<bool, Box<dyn Error>> {
+        let mask_value = self.masked_seed(seed)?;
+        self.check_key(key)?;
+        if response.len() != self.entry.response_len {
+            return Ok(false);
+        }
+
+        Ok(self.algorithm.verify(&mask_value, key, self.entry.cipher, response))
+    }
+
+    /// Calculates the key response from hex encoded seed and key strings
+    ///
+    /// # Returns
+    /// * `Result<SecretString, Box<dyn Error>>` - The calculated key response as a hex string or an error
+    pub fn calculate_hex(&self, seed_input: &str, key_input: &str) -> Result<SecretString, Box<dyn Error>> {
+        let (seed, key) = self.parse_inputs(seed_input, key_input)?;
+
+        Ok(bytes_to_secret_hex(&self.calculate(&seed, &key)?))
+    }
+
+    /// Checks a recorded key response against hex encoded seed and key strings
+    ///
+    /// # Returns
+    /// * `Result<bool, Box<dyn Error>>` - Whether the response matches, or an error if an input is invalid
+    pub fn verify_hex(&self, seed_input: &str, key_input: &str, response_input: &str) -> Result<bool, Box<dyn Error>> {
+        let (seed, key) = self.parse_inputs(seed_input, key_input)?;
+
+        if response_input.is_empty() {
+            return Err("输入的Key响应不能为空".into());
+        }
+        let response = parse_hex_input(response_input).map_err(|e| match e {
+            HexInputError::InvalidChar { position, character } => invalid_char("Key响应", position, character),
+            _ => String::from("无效的Key响应：必须是十六进制字符串"),
+        })?;
+
+        self.verify(&seed, &key, &response)
+    }
+
+    /// Validates and converts hex encoded seed and key strings to bytes, accepting
+    /// every form of [`parse_hex_input`]
+    fn parse_inputs(&self, seed_input: &str, key_input: &str) -> Result<(SecretBytes, SecretBytes), Box<dyn Error>> {
+        // Validate and convert seed to bytes
+        if seed_input.is_empty() {
+            return Err("输入的Seed不能为空".into());
+        }
+
+        let seed = match parse_hex_input(seed_input) {
+            Ok(seed) if seed.len() == self.seed_len() => seed,
+            Err(HexInputError::InvalidChar { position, character }) => return Err(invalid_char("Seed", position, character).into()),
+            _ => return Err(format!("无效的Seed输入：必须是{}个字符的十六进制字符串", self.seed_len() * 2).into()),
+        };
+
+        // Validate and convert key to bytes
+        if key_input.is_empty() {
+            return Err("输入的Key不能为空".into());
+        }
+
+        let key = match parse_hex_input(key_input) {
+            Ok(key) if self.check_key(&key).is_ok() => key,
+            Err(HexInputError::InvalidChar { position, character }) => return Err(invalid_char("Key", position, character).into()),
+            _ => return Err(self.key_hint().into()),
+        };
+
+        Ok((seed, key))
+    }
+
+    /// Describes the expected key input of the algorithm
+    fn key_hint(&self) -> String {
+        let lengths: Vec<String> = self.key_lengths().iter().map(|len| (len * 2).to_string()).collect();
+        match lengths.split_last() {
+            None => String::from("无效的Key输入：必须是非空的十六进制字符串"),
+            Some((last, [])) => format!("无效的Key输入：必须是{}个字符的十六进制字符串", last),
+            Some((last, rest)) => format!("无效的Key输入：必须是{}或{}个字符的十六进制字符串", rest.join("、"), last),
+        }
+    }
+
+    /// XORs the seed with the configured mask
+    fn masked_seed(&self, seed: &[u8]) -> Result<SecretBytes, Box<dyn Error>> {
+        if seed.len() != self.seed_len() {
+            return Err(format!("Seed长度必须为{}个字节", self.seed_len()).into());
+        }
+
+        xor_bytes(seed, &self.entry.mask).map_err(|e| format!("异或操作失败: {}", e).into())
+    }
+}
+
+/// Describes an invalid character of a hex input field
+fn invalid_char(field: &str, position: usize, character: char) -> String {
+    format!("无效的{}输入：第{}个字符“{}”不是十六进制数字", field, position, character)
+}
+
+/// Returns the entries of the table whose mask and algorithm produce `response`
+/// from `seed` and `key`, to find out which ECU or level a rejected key was
+/// calculated for
+pub fn find_matching_entries<'a>(table: &'a MaskTable, seed: &[u8], key: &[u8], response: &[u8]) -> Vec<&'a MaskEntry> {
+    table
+        .entries()
+        .iter()
+        .filter(|entry| entry.seed_len() == seed.len() && entry.response_len == response.len())
+        .filter(|entry| {
+            SeedKeyCalculator::from_table(table, &entry.ecu, entry.level)
+                .and_then(|calculator| calculator.verify(seed, key, response))
+                .unwrap_or(false)
+        })
+        .collect()
+}
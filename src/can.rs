@@ -0,0 +1,187 @@
+//! Classic CAN frames and the Linux SocketCAN interface.
+
+use std::io;
+use std::time::Duration;
+
+/// Largest standard (11 bit) CAN identifier, larger identifiers are sent as extended (29 bit) frames
+pub const MAX_STANDARD_ID: u32 = 0x7FF;
+
+/// Largest extended (29 bit) CAN identifier
+pub const MAX_EXTENDED_ID: u32 = 0x1FFF_FFFF;
+
+/// A classic CAN data frame of up to 8 bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CanFrame {
+    pub id: u32,
+    pub data: Vec<u8>,
+}
+
+impl CanFrame {
+    pub fn new(id: u32, data: &[u8]) -> Self {
+        Self { id, data: data.to_vec() }
+    }
+}
+
+/// A CAN bus the ISO-TP transport sends and receives frames on
+pub trait CanInterface {
+    fn send(&mut self, frame: &CanFrame) -> io::Result<()>;
+
+    /// Waits up to `timeout` for the next frame, `None` on timeout
+    fn recv(&mut self, timeout: Duration) -> io::Result<Option<CanFrame>>;
+}
+
+/// Parses a CAN identifier in hex (`0x7E0`) or decimal notation
+pub fn parse_can_id(text: &str) -> Result<u32, String> {
+    let id = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse::<u32>(),
+    }
+    .map_err(|_| format!("invalid CAN identifier `{}`", text))?;
+
+    if id > MAX_EXTENDED_ID {
+        return Err(format!("CAN identifier 0x{:X} exceeds 29 bits", id));
+    }
+    Ok(id)
+}
+
+#[cfg(target_os = "linux")]
+pub use socketcan::SocketCan;
+
+#[cfg(target_os = "linux")]
+mod socketcan {
+    use std::ffi::CString;
+    use std::io;
+    use std::mem;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::time::Duration;
+
+    use super::{CanFrame, CanInterface, MAX_STANDARD_ID};
+
+    /// Raw CAN socket bound to an interface such as `can0` or `vcan0`
+    #[derive(Debug)]
+    pub struct SocketCan {
+        fd: OwnedFd,
+    }
+
+    impl SocketCan {
+        /// Opens a raw CAN socket on the interface
+        pub fn open(interface: &str) -> io::Result<Self> {
+            let name = CString::new(interface).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+            if index == 0 {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "no such CAN interface"));
+            }
+
+            let fd = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::CAN_RAW) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            let mut addr: libc::sockaddr_can = unsafe { mem::zeroed() };
+            addr.can_family = libc::AF_CAN as libc::sa_family_t;
+            addr.can_ifindex = index as libc::c_int;
+            let result = unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    &addr as *const libc::sockaddr_can as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_can>() as libc::socklen_t,
+                )
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self { fd })
+        }
+
+        /// Only receives frames with the given identifiers
+        pub fn set_filter(&self, ids: &[u32]) -> io::Result<()> {
+            let filters: Vec<libc::can_filter> = ids
+                .iter()
+                .map(|&id| libc::can_filter {
+                    can_id: raw_id(id),
+                    can_mask: libc::CAN_EFF_FLAG | libc::CAN_RTR_FLAG | libc::CAN_EFF_MASK,
+                })
+                .collect();
+
+            let result = unsafe {
+                libc::setsockopt(
+                    self.fd.as_raw_fd(),
+                    libc::SOL_CAN_RAW,
+                    libc::CAN_RAW_FILTER,
+                    filters.as_ptr() as *const libc::c_void,
+                    mem::size_of_val(filters.as_slice()) as libc::socklen_t,
+                )
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    /// Adds the extended frame flag to identifiers that do not fit in 11 bits
+    fn raw_id(id: u32) -> libc::canid_t {
+        if id > MAX_STANDARD_ID { id | libc::CAN_EFF_FLAG } else { id }
+    }
+
+    impl CanInterface for SocketCan {
+        fn send(&mut self, frame: &CanFrame) -> io::Result<()> {
+            if frame.data.len() > libc::CAN_MAX_DLEN {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "CAN frame longer than 8 bytes"));
+            }
+
+            let mut raw: libc::can_frame = unsafe { mem::zeroed() };
+            raw.can_id = raw_id(frame.id);
+            raw.can_dlc = frame.data.len() as u8;
+            raw.data[..frame.data.len()].copy_from_slice(&frame.data);
+
+            let size = mem::size_of::<libc::can_frame>();
+            let written = unsafe {
+                libc::write(self.fd.as_raw_fd(), &raw as *const libc::can_frame as *const libc::c_void, size)
+            };
+            if written < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        fn recv(&mut self, timeout: Duration) -> io::Result<Option<CanFrame>> {
+            loop {
+                let mut pollfd = libc::pollfd { fd: self.fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+                let ready = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis().min(i32::MAX as u128) as libc::c_int) };
+                if ready < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(err);
+                }
+                if ready == 0 {
+                    return Ok(None);
+                }
+
+                let mut raw: libc::can_frame = unsafe { mem::zeroed() };
+                let size = mem::size_of::<libc::can_frame>();
+                let read = unsafe { libc::read(self.fd.as_raw_fd(), &mut raw as *mut libc::can_frame as *mut libc::c_void, size) };
+                if read < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                // Error and remote frames carry no ISO-TP data
+                if raw.can_id & (libc::CAN_ERR_FLAG | libc::CAN_RTR_FLAG) != 0 {
+                    continue;
+                }
+
+                let id = if raw.can_id & libc::CAN_EFF_FLAG != 0 {
+                    raw.can_id & libc::CAN_EFF_MASK
+                } else {
+                    raw.can_id & libc::CAN_SFF_MASK
+                };
+                let len = (raw.can_dlc as usize).min(libc::CAN_MAX_DLEN);
+                return Ok(Some(CanFrame::new(id, &raw.data[..len])));
+            }
+        }
+    }
+}
@@ -1,53 +1,468 @@
-// src/cli.rs
-use clap::{Arg, Command};
-use std::error::Error;
-
-#[derive(Debug)]
-pub struct CliArg {
-    pub seed: String,
-    pub key: String,   
-}
-
-impl CliArg {
-    pub fn new(seed: String, key: String) -> Self {
-        Self { seed, key}
-    }
-}
-
-pub fn parse_cli() -> Result<CliArg, Box<dyn Error>> {
-    let matches = Command::new("Seed2CMAC")
-        .version("0.1.0")
-        .author("Quinn")
-        .about("A tool to generate CMAC from seed.")
-        .arg(
-            Arg::new("seed")
-                .short('s')
-                .long("seed")
-                .value_name("SEED")
-                .help("Set the seed value")
-                .value_parser(clap::value_parser!(String)),
-        )
-        .arg(
-            Arg::new("key")
-                .short('k')
-                .long("key")
-                .value_name("KEY")
-                .help("Set the key value")
-                .value_parser(clap::value_parser!(String)),
-        )
-        .get_matches();
-
-    if !matches.args_present() {
-        println!("Usage: seed2cmac [OPTIONS]");
-        println!("Options:");
-        println!("  -s, --seed <SEED>    Set the seed value");
-        println!("  -k, --key <KEY>      Set the key value");
-        println!("  -h, --help           Print help information");
-        return Err("Invalid input".into());
-    }
-
-    let seed = matches.get_one::<String>("seed").ok_or("seed not found")?.to_owned();
-    let key = matches.get_one::<String>("key").ok_or("key not found")?.to_owned();
-
-    Ok(CliArg::new(seed, key))
-}
+// src/cli.rs
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{Arg, ArgAction, ArgGroup, Command};
+use seed2cmac::can::parse_can_id;
+use seed2cmac::doip::{parse_logical_address, with_default_port, DEFAULT_TESTER_ADDRESS};
+use seed2cmac::service::DEFAULT_ADDR;
+use seed2cmac::util::{OutputFormat, SecretString};
+use seed2cmac::SecurityLevel;
+
+#[derive(Debug)]
+pub struct CliArg {
+    pub mask_files: Vec<PathBuf>,
+    /// Arguments of the headless mode, `None` when the GUI should be started
+    pub headless: Option<HeadlessArg>,
+    /// Arguments for unlocking an ECU on the CAN bus or over TCP
+    pub unlock: Option<UnlockArg>,
+    /// Arguments for running a simulated ECU
+    pub simulate: Option<SimulateArg>,
+    /// Arguments for calculating the keys of a CSV or JSON Lines file
+    pub batch: Option<BatchArg>,
+    /// Arguments for serving key calculations over HTTP
+    pub serve: Option<ServeArg>,
+    /// Key store file given with `--key-store`, `None` for the default one
+    pub key_store: Option<PathBuf>,
+    /// Key store management instead of a key calculation
+    pub key_store_action: Option<KeyStoreArg>,
+    /// Audit log given with `--audit-log`, `None` for the default one
+    pub audit_log: Option<PathBuf>,
+    /// Checks the hash chain of the audit log instead of a key calculation
+    pub verify_audit_log: bool,
+}
+
+#[derive(Debug)]
+pub struct HeadlessArg {
+    pub ecu: String,
+    pub level: SecurityLevel,
+    pub seed: String,
+    pub key: SecretString,
+    /// Recorded key response to verify instead of calculating one
+    pub response: Option<String>,
+    /// Format the calculated response is printed in
+    pub format: OutputFormat,
+}
+
+impl HeadlessArg {
+    pub fn new(ecu: String, level: SecurityLevel, seed: String, key: SecretString) -> Self {
+        Self { ecu, level, seed, key, response: None, format: OutputFormat::default() }
+    }
+}
+
+/// Key calculation for every row of a batch file
+#[derive(Debug)]
+pub struct BatchArg {
+    pub input: PathBuf,
+    /// Output file, by default next to the input
+    pub output: Option<PathBuf>,
+    /// Key of the rows without one
+    pub key: Option<SecretString>,
+    /// Format of the response column
+    pub format: OutputFormat,
+}
+
+/// Change to the key store
+#[derive(Debug)]
+pub enum KeyStoreArg {
+    /// Adds or replaces the key of the alias, read from stdin
+    Add(String),
+    Remove(String),
+    List,
+}
+
+/// Local HTTP/JSON key service
+#[derive(Debug)]
+pub struct ServeArg {
+    pub addr: String,
+    /// Bearer token every request must carry, `None` to accept all requests
+    pub token: Option<String>,
+}
+
+/// Connection to the ECU, or of the simulated ECU to the tester
+#[derive(Debug)]
+pub enum Link {
+    /// ISO-TP on a SocketCAN interface, `tx_id` and `rx_id` as seen from the tester
+    Can { interface: String, tx_id: u32, rx_id: u32 },
+    /// Length prefixed UDS messages on a TCP connection
+    Tcp(String),
+    /// Diagnostic messages through a DoIP entity, `ecu_address` `None` to use
+    /// the logical address of the entity
+    DoIp { addr: String, tester_address: u16, ecu_address: Option<u16> },
+}
+
+/// Security access to an ECU over ISO-TP on a SocketCAN interface or over TCP
+#[derive(Debug)]
+pub struct UnlockArg {
+    pub ecu: String,
+    pub level: SecurityLevel,
+    pub key: SecretString,
+    pub link: Link,
+    /// Format the sent key is printed in
+    pub format: OutputFormat,
+}
+
+/// Simulated ECU answering security access requests
+#[derive(Debug)]
+pub struct SimulateArg {
+    pub ecu: String,
+    pub key: SecretString,
+    pub link: Link,
+    pub max_attempts: u32,
+    pub delay: Duration,
+}
+
+/// Parses the command line, exiting with usage information on invalid input
+pub fn parse_cli() -> CliArg {
+    let matches = Command::new("Seed2CMAC")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Quinn")
+        .about("A tool to generate CMAC from seed. Starts the GUI unless --ecu, --level, --key and --seed, --can, --tcp or --doip, --batch or --serve are given.")
+        .arg(
+            Arg::new("mask-file")
+                .short('m')
+                .long("mask-file")
+                .value_name("PATH")
+                .help("Load an additional mask file, may be repeated")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("ecu")
+                .short('e')
+                .long("ecu")
+                .value_name("ECU")
+                .help("Set the ECU type, e.g. BMS")
+                .requires_all(["key", "source"])
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("level")
+                .short('l')
+                .long("level")
+                .value_name("LEVEL")
+                .help("Set the security level in decimal or hex notation, e.g. 1 or 0x11")
+                .requires("ecu")
+                .value_parser(|s: &str| s.parse::<SecurityLevel>().map_err(|e| e.to_string())),
+        )
+        .arg(
+            Arg::new("seed")
+                .short('s')
+                .long("seed")
+                .value_name("SEED")
+                .help("Set the seed value")
+                .requires("ecu")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("key")
+                .short('k')
+                .long("key")
+                .value_name("KEY")
+                .help("Set the key value in hex or as @ALIAS of the key store, the default key of rows without one with --batch")
+                .requires("mode")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("response")
+                .short('r')
+                .long("response")
+                .value_name("RESPONSE")
+                .help("Verify a recorded key response instead of calculating one")
+                .requires("ecu")
+                .conflicts_with_all(["can", "tcp", "doip"])
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("can")
+                .long("can")
+                .value_name("INTERFACE")
+                .help("Unlock the ECU over ISO-TP on a SocketCAN interface, e.g. can0 or vcan0, instead of taking --seed")
+                .requires("ecu")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("tx-id")
+                .long("tx-id")
+                .value_name("ID")
+                .help("CAN identifier of requests to the ECU")
+                .default_value("0x7E0")
+                .value_parser(parse_can_id),
+        )
+        .arg(
+            Arg::new("rx-id")
+                .long("rx-id")
+                .value_name("ID")
+                .help("CAN identifier of responses from the ECU")
+                .default_value("0x7E8")
+                .value_parser(parse_can_id),
+        )
+        .arg(
+            Arg::new("tcp")
+                .long("tcp")
+                .value_name("ADDR")
+                .help("Unlock the ECU over TCP, e.g. 127.0.0.1:13401, instead of taking --seed")
+                .requires("ecu")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("doip")
+                .long("doip")
+                .value_name("ADDR")
+                .help("Unlock the ECU over DoIP (ISO 13400) through the entity at ADDR, port 13400 unless given, instead of taking --seed")
+                .requires("ecu")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("ecu-address")
+                .long("ecu-address")
+                .value_name("ADDR")
+                .help("DoIP logical address of the ECU, by default the address the entity reports in vehicle identification")
+                .requires("doip")
+                .value_parser(parse_logical_address),
+        )
+        .arg(
+            Arg::new("tester-address")
+                .long("tester-address")
+                .value_name("ADDR")
+                .help("DoIP logical address of the tester")
+                .default_value("0x0E00")
+                .value_parser(parse_logical_address),
+        )
+        .arg(
+            Arg::new("simulate")
+                .long("simulate")
+                .help("Run a simulated ECU answering security access on --can, --listen or --doip with the keys calculated from --key")
+                .requires("ecu")
+                .conflicts_with_all(["level", "seed", "response", "tcp"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("listen")
+                .long("listen")
+                .value_name("ADDR")
+                .help("Accept testers of the simulated ECU on a TCP address, e.g. 127.0.0.1:13401")
+                .requires("simulate")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("max-attempts")
+                .long("max-attempts")
+                .value_name("N")
+                .help("Invalid keys the simulated ECU accepts before refusing seeds")
+                .default_value("3")
+                .value_parser(clap::value_parser!(u32).range(1..)),
+        )
+        .arg(
+            Arg::new("delay")
+                .long("delay")
+                .value_name("SECONDS")
+                .help("Time the simulated ECU refuses seeds after too many invalid keys")
+                .default_value("10")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("batch")
+                .short('b')
+                .long("batch")
+                .value_name("FILE")
+                .help("Calculate the keys of every ecu,level,seed[,key] row of a CSV or JSON Lines (.jsonl) file")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Write the batch results to FILE instead of <FILE>.keys.csv or <FILE>.keys.jsonl next to the input")
+                .requires("batch")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_name("FORMAT")
+                .help("Print or write the key response as hex (a1b2), upper (A1B2), spaced (A1 B2), c-list (0xA1, 0xB2), uds (27 02 A1 B2) or base64")
+                .requires("mode")
+                .default_value("hex")
+                .value_parser(
+                    PossibleValuesParser::new(OutputFormat::ALL.map(OutputFormat::name))
+                        .map(|s| s.parse::<OutputFormat>().expect("possible value")),
+                ),
+        )
+        .arg(
+            Arg::new("serve")
+                .long("serve")
+                .value_name("ADDR")
+                .help("Serve POST /v1/key and GET /v1/ecus over HTTP on ADDR, 127.0.0.1:8731 if omitted")
+                .num_args(0..=1)
+                .default_missing_value(DEFAULT_ADDR)
+                .conflicts_with_all(["ecu", "batch"])
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("token")
+                .long("token")
+                .value_name("TOKEN")
+                .help("Require `Authorization: Bearer TOKEN` on every request, also read from SEED2CMAC_TOKEN")
+                .requires("serve")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("key-store")
+                .long("key-store")
+                .value_name("PATH")
+                .help("Use this key store instead of keys.json in the seed2cmac config directory")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("add-key")
+                .long("add-key")
+                .value_name("ALIAS")
+                .help("Add the key read from stdin or a hidden prompt to the key store as ALIAS, e.g. BMS/P1/prod")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("remove-key")
+                .long("remove-key")
+                .value_name("ALIAS")
+                .help("Remove ALIAS from the key store")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("list-keys")
+                .long("list-keys")
+                .help("List the aliases of the key store")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("audit-log")
+                .long("audit-log")
+                .value_name("PATH")
+                .help("Record key calculations in this audit log instead of audit.jsonl in the seed2cmac config directory")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("verify-audit-log")
+                .long("verify-audit-log")
+                .help("Check the hash chain of the audit log")
+                .conflicts_with_all(["ecu", "batch", "serve", "key-store-action"])
+                .action(ArgAction::SetTrue),
+        )
+        .group(ArgGroup::new("key-store-action").args(["add-key", "remove-key", "list-keys"]).conflicts_with_all(["ecu", "batch", "serve"]))
+        .group(ArgGroup::new("mode").args(["ecu", "batch"]))
+        .group(ArgGroup::new("source").args(["seed", "can", "tcp", "listen", "doip"]))
+        .after_help(
+            "Exit codes:\n  \
+             0  Success, or the response matches with --response\n  \
+             1  The response does not match\n  \
+             2  Invalid command line\n  \
+             3  Unknown ECU type\n  \
+             4  Security level not supported by the ECU\n  \
+             5  No mask configured for the ECU and security level\n  \
+             6  Invalid seed\n  \
+             7  Invalid key\n  \
+             8  Key calculation failed\n  \
+             9  Invalid mask file\n  \
+             10 Invalid response\n  \
+             11 Communication with the ECU failed\n  \
+             12 ECU sent a negative response\n  \
+             13 Batch file could not be read or written\n  \
+             14 Some batch rows failed, see the error column of the output\n  \
+             15 Key service could not be started\n  \
+             16 Key store could not be opened or saved, or has no such alias\n  \
+             17 Audit log could not be written, or its hash chain is broken",
+        )
+        .get_matches();
+
+    // Every mode but the simulator needs a security level
+    if matches.contains_id("ecu") && !matches.get_flag("simulate") && !matches.contains_id("level") {
+        clap::Error::raw(clap::error::ErrorKind::MissingRequiredArgument, "--level is required with --ecu\n").exit();
+    }
+
+    let mask_files = matches
+        .get_many::<PathBuf>("mask-file")
+        .map(|files| files.cloned().collect())
+        .unwrap_or_default();
+
+    let ecu = matches.get_one::<String>("ecu");
+    let level = || *matches.get_one::<SecurityLevel>("level").expect("required");
+    let key = || SecretString::from(matches.get_one::<String>("key").expect("required").as_str());
+
+    let link = matches
+        .get_one::<String>("can")
+        .map(|interface| Link::Can {
+            interface: interface.to_owned(),
+            tx_id: *matches.get_one::<u32>("tx-id").expect("default"),
+            rx_id: *matches.get_one::<u32>("rx-id").expect("default"),
+        })
+        .or_else(|| matches.get_one::<String>("tcp").or(matches.get_one::<String>("listen")).cloned().map(Link::Tcp))
+        .or_else(|| {
+            matches.get_one::<String>("doip").map(|addr| Link::DoIp {
+                addr: with_default_port(addr),
+                tester_address: matches.get_one::<u16>("tester-address").copied().unwrap_or(DEFAULT_TESTER_ADDRESS),
+                ecu_address: matches.get_one::<u16>("ecu-address").copied(),
+            })
+        });
+
+    let format = *matches.get_one::<OutputFormat>("format").expect("default");
+    let (unlock, simulate) = match ecu.zip(link) {
+        Some((ecu, link)) if matches.get_flag("simulate") => {
+            let simulate = SimulateArg {
+                ecu: ecu.to_owned(),
+                key: key(),
+                link,
+                max_attempts: *matches.get_one::<u32>("max-attempts").expect("default"),
+                delay: Duration::from_secs(*matches.get_one::<u64>("delay").expect("default")),
+            };
+            (None, Some(simulate))
+        }
+        Some((ecu, link)) => (Some(UnlockArg { ecu: ecu.to_owned(), level: level(), key: key(), link, format }), None),
+        None => (None, None),
+    };
+
+    let headless = ecu.zip(matches.get_one::<String>("seed")).map(|(ecu, seed)| {
+        let mut arg = HeadlessArg::new(ecu.to_owned(), level(), seed.to_owned(), key());
+        arg.response = matches.get_one::<String>("response").cloned();
+        arg.format = format;
+        arg
+    });
+
+    let batch = matches.get_one::<PathBuf>("batch").map(|input| BatchArg {
+        input: input.to_owned(),
+        output: matches.get_one::<PathBuf>("output").cloned(),
+        key: matches.get_one::<String>("key").map(|key| SecretString::from(key.as_str())),
+        format,
+    });
+
+    let serve = matches.get_one::<String>("serve").map(|addr| ServeArg {
+        addr: addr.to_owned(),
+        token: matches
+            .get_one::<String>("token")
+            .cloned()
+            .or_else(|| std::env::var("SEED2CMAC_TOKEN").ok())
+            .filter(|token| !token.is_empty()),
+    });
+
+    let key_store_action = matches
+        .get_one::<String>("add-key")
+        .cloned()
+        .map(KeyStoreArg::Add)
+        .or_else(|| matches.get_one::<String>("remove-key").cloned().map(KeyStoreArg::Remove))
+        .or_else(|| matches.get_flag("list-keys").then_some(KeyStoreArg::List));
+
+    CliArg {
+        mask_files,
+        headless,
+        unlock,
+        simulate,
+        batch,
+        serve,
+        key_store: matches.get_one::<PathBuf>("key-store").cloned(),
+        key_store_action,
+        audit_log: matches.get_one::<PathBuf>("audit-log").cloned(),
+        verify_audit_log: matches.get_flag("verify-audit-log"),
+    }
+}
@@ -0,0 +1,106 @@
+//! UDS client unlocking an ECU with SecurityAccess (0x27).
+
+use std::error::Error;
+use std::time::Duration;
+
+use crate::calculator::SeedKeyCalculator;
+use crate::can::CanInterface;
+use crate::isotp::IsoTp;
+use crate::uds::{self, NegativeResponse, NEGATIVE_RESPONSE};
+use crate::util::SecretBytes;
+
+/// DiagnosticSessionControl request for the extended diagnostic session
+pub const EXTENDED_SESSION: [u8; 2] = [0x10, 0x03];
+
+/// NRC requestCorrectlyReceivedResponsePending, the final response follows later
+const RESPONSE_PENDING: u8 = 0x78;
+
+/// Time the ECU has to start its response to a request (P2), with some margin
+/// over the 50 ms of ISO 14229-2 for gateways and USB adapters
+pub const P2_TIMEOUT: Duration = Duration::from_millis(150);
+
+/// Time the ECU has for the next response after a response pending (P2*)
+pub const P2_STAR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Transport of UDS messages, e.g. ISO-TP on CAN
+pub trait UdsTransport {
+    fn send(&mut self, message: &[u8]) -> Result<(), Box<dyn Error>>;
+
+    /// Receives the next message, waiting up to `timeout` for it to start.
+    /// Fails with [`std::io::ErrorKind::TimedOut`] if it does not.
+    fn receive(&mut self, timeout: Duration) -> Result<Vec<u8>, Box<dyn Error>>;
+}
+
+impl<C: CanInterface> UdsTransport for IsoTp<C> {
+    fn send(&mut self, message: &[u8]) -> Result<(), Box<dyn Error>> {
+        IsoTp::send(self, message)
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Result<Vec<u8>, Box<dyn Error>> {
+        IsoTp::receive_within(self, timeout)
+    }
+}
+
+/// Outcome of a successful security access
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnlockReport {
+    /// Seed sent by the ECU
+    pub seed: Vec<u8>,
+    /// Key sent to the ECU, `None` if the ECU was already unlocked and sent an all-zero seed
+    pub key: Option<SecretBytes>,
+}
+
+/// Sends a request and waits for its final response, skipping response pending messages.
+///
+/// The response must start within [`P2_TIMEOUT`], and within [`P2_STAR_TIMEOUT`]
+/// after each response pending.
+pub fn request(transport: &mut dyn UdsTransport, message: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    transport.send(message)?;
+    let mut timeout = P2_TIMEOUT;
+    loop {
+        let response = transport.receive(timeout)?;
+        match response.as_slice() {
+            [NEGATIVE_RESPONSE, sid, RESPONSE_PENDING] if Some(sid) == message.first() => timeout = P2_STAR_TIMEOUT,
+            _ => return Ok(response),
+        }
+    }
+}
+
+/// Switches the ECU to the extended diagnostic session
+pub fn start_extended_session(transport: &mut dyn UdsTransport) -> Result<(), Box<dyn Error>> {
+    let response = request(transport, &EXTENDED_SESSION)?;
+    match response.as_slice() {
+        [0x50, 0x03, ..] => Ok(()),
+        [NEGATIVE_RESPONSE, sid, nrc, ..] => Err(NegativeResponse { sid: *sid, nrc: *nrc }.into()),
+        _ => Err(format!("Unexpected DiagnosticSessionControl response: {:02X?}", response).into()),
+    }
+}
+
+/// Unlocks the ECU at the security level of the calculator: enters the extended
+/// session, requests a seed, calculates the key from it and sends the key.
+///
+/// A rejected request is reported as [`NegativeResponse`], e.g. NRC 0x35
+/// (invalidKey), 0x36 (exceededNumberOfAttempts) or 0x37 (requiredTimeDelayNotExpired).
+pub fn unlock(
+    transport: &mut dyn UdsTransport,
+    calculator: &SeedKeyCalculator,
+    key: &[u8],
+) -> Result<UnlockReport, Box<dyn Error>> {
+    let level = calculator.level();
+    start_extended_session(transport)?;
+
+    let response = uds::parse_seed_response(&request(transport, &uds::request_seed(level))?)?;
+    if response.level != level {
+        return Err(format!("Seed response for security level {}, requested {}", response.level, level).into());
+    }
+
+    // An all-zero seed means the level is already unlocked
+    if response.seed.iter().all(|b| *b == 0) {
+        return Ok(UnlockReport { seed: response.seed, key: None });
+    }
+
+    let response_key = calculator.calculate(&response.seed, key)?;
+    uds::parse_send_key_response(&request(transport, &uds::send_key(level, &response_key))?, level)?;
+
+    Ok(UnlockReport { seed: response.seed, key: Some(response_key) })
+}
@@ -1,56 +1,145 @@
-use aes::Aes128;
-use cmac::{Cmac, Mac}; 
-use std::error::Error;
-
-// Import from parent crate
-use crate::util::{hex_string_to_bytes, bytes_to_hex_string};
-
-/// Calculates CMAC using AES-128
-/// 
-/// # Arguments
-/// * `key` - The key bytes for CMAC calculation
-/// * `data` - The data to calculate CMAC for
-/// 
-/// # Returns
-/// * `Result<Vec<u8>, Box<dyn Error>>` - The calculated CMAC bytes or an error
-pub fn calculate_cmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
-    let mut mac = Cmac::<Aes128>::new_from_slice(key)
-        .map_err(|e| format!("Invalid key length: {:?}", e))?;
-
-    mac.update(data);
-    let result = mac.finalize().into_bytes();
-    
-    Ok(result.to_vec())
-}
-
-/// Calculates the CMAC key based on key and mask_value
-/// 
-/// # Arguments
-/// * `key_input` - Hex string of the key
-/// * `mask_value` - Pre-calculated mask value (after XOR operation)
-/// 
-/// # Returns
-/// * `Result<String, Box<dyn Error>>` - The calculated CMAC key as a hex string or an error
-pub fn calculate_cmac_key(
-    key_input: &str,
-    mask_value: &[u8]
-) -> Result<String, Box<dyn Error>> {
-    // Validate and convert key to bytes
-    if key_input.is_empty() {
-        return Err("输入的Key不能为空".into());
-    }
-    
-    let key = match hex_string_to_bytes(key_input) {
-        Ok(bytes) => bytes,
-        Err(_) => return Err("无效的Key输入：必须是32个字符的十六进制字符串".into()),
-    };
-    
-    // Calculate CMAC
-    let cmac = match calculate_cmac(&key, mask_value) {
-        Ok(bytes) => bytes,
-        Err(e) => return Err(format!("CMAC计算失败: {}", e).into()),
-    };
-    
-    // Convert result to hex string
-    Ok(bytes_to_hex_string(&cmac))
-}
+use aes::{Aes128, Aes192, Aes256};
+use cmac::{Cmac, Mac};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+// Import from parent crate
+use crate::util::SecretBytes;
+
+mod algorithm;
+
+pub use algorithm::{algorithm, algorithms, AesEcb, HmacSha256, MaskCmac, SeedKeyAlgorithm, ShiftXor32};
+
+/// Block cipher used for CMAC calculation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CmacCipher {
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl CmacCipher {
+    pub const ALL: [CmacCipher; 3] = [CmacCipher::Aes128, CmacCipher::Aes192, CmacCipher::Aes256];
+
+    /// Returns the key length of the cipher in bytes
+    pub fn key_len(self) -> usize {
+        match self {
+            CmacCipher::Aes128 => 16,
+            CmacCipher::Aes192 => 24,
+            CmacCipher::Aes256 => 32,
+        }
+    }
+
+    /// Returns the cipher whose key length is `len` bytes
+    pub fn from_key_len(len: usize) -> Option<Self> {
+        Self::ALL.into_iter().find(|cipher| cipher.key_len() == len)
+    }
+}
+
+impl fmt::Display for CmacCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AES-{}", self.key_len() * 8)
+    }
+}
+
+impl FromStr for CmacCipher {
+    type Err = Box<dyn Error>;
+
+    /// Parses `aes128`, `AES-192`, `aes256` and so on
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|cipher| s.replace('-', "").eq_ignore_ascii_case(&cipher.to_string().replace('-', "")))
+            .ok_or_else(|| format!("Unknown cipher: {}", s).into())
+    }
+}
+
+/// Selects the cipher for a key, checking the key length against the cipher if
+/// one is configured and otherwise choosing the cipher by key length
+pub fn select_cipher(cipher: Option<CmacCipher>, key_len: usize) -> Result<CmacCipher, Box<dyn Error>> {
+    match cipher {
+        Some(cipher) if cipher.key_len() == key_len => Ok(cipher),
+        Some(cipher) => Err(format!(
+            "{} requires a {} byte key, got {} bytes",
+            cipher,
+            cipher.key_len(),
+            key_len
+        )
+        .into()),
+        None => CmacCipher::from_key_len(key_len).ok_or_else(|| {
+            format!("Invalid key length: {} bytes, expected 16, 24 or 32", key_len).into()
+        }),
+    }
+}
+
+/// Calculates CMAC using AES-128, AES-192 or AES-256 chosen by the key length
+///
+/// # Arguments
+/// * `key` - The key bytes for CMAC calculation
+/// * `data` - The data to calculate CMAC for
+///
+/// # Returns
+/// * `Result<SecretBytes, Box<dyn Error>>` - The calculated CMAC bytes or an error
+pub fn calculate_cmac(key: &[u8], data: &[u8]) -> Result<SecretBytes, Box<dyn Error>> {
+    calculate_cmac_with(select_cipher(None, key.len())?, key, data)
+}
+
+/// Calculates CMAC using the given cipher
+///
+/// # Arguments
+/// * `cipher` - The block cipher, the key must have its key length
+/// * `key` - The key bytes for CMAC calculation
+/// * `data` - The data to calculate CMAC for
+///
+/// # Returns
+/// * `Result<SecretBytes, Box<dyn Error>>` - The calculated CMAC bytes or an error
+pub fn calculate_cmac_with(cipher: CmacCipher, key: &[u8], data: &[u8]) -> Result<SecretBytes, Box<dyn Error>> {
+    select_cipher(Some(cipher), key.len())?;
+
+    match cipher {
+        CmacCipher::Aes128 => mac::<Cmac<Aes128>>(key, data),
+        CmacCipher::Aes192 => mac::<Cmac<Aes192>>(key, data),
+        CmacCipher::Aes256 => mac::<Cmac<Aes256>>(key, data),
+    }
+}
+
+/// Checks a recorded key response against the CMAC of `data`
+///
+/// The response may be truncated, in which case it is compared with the leading
+/// bytes of the CMAC. The comparison runs in constant time.
+///
+/// # Arguments
+/// * `key` - The key bytes for CMAC calculation
+/// * `data` - The data the response was calculated for, i.e. the masked seed
+/// * `response` - The recorded key response
+///
+/// # Returns
+/// * `Result<bool, Box<dyn Error>>` - Whether the response matches, or an error if the CMAC cannot be calculated
+pub fn verify(key: &[u8], data: &[u8], response: &[u8]) -> Result<bool, Box<dyn Error>> {
+    let cmac = calculate_cmac(key, data)?;
+    if response.is_empty() || response.len() > cmac.len() {
+        return Ok(false);
+    }
+
+    Ok(constant_time_eq(&cmac[..response.len()], response))
+}
+
+/// Compares two byte strings in time independent of their content
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+fn mac<M: Mac + cmac::digest::KeyInit>(key: &[u8], data: &[u8]) -> Result<SecretBytes, Box<dyn Error>> {
+    let mut mac = <M as Mac>::new_from_slice(key)
+        .map_err(|e| format!("Invalid key length: {:?}", e))?;
+
+    mac.update(data);
+    let mut result = mac.finalize().into_bytes();
+    let bytes = SecretBytes::from(result.as_slice());
+    result.as_mut_slice().zeroize();
+
+    Ok(bytes)
+}
@@ -0,0 +1,222 @@
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::{Aes128, Aes192, Aes256};
+use hmac::Hmac;
+use sha2::Sha256;
+use std::error::Error;
+use std::fmt;
+
+use super::{calculate_cmac_with, constant_time_eq, mac, select_cipher, CmacCipher};
+use crate::mask::CMAC_LEN;
+use crate::util::SecretBytes;
+
+/// A seed-to-key algorithm.
+///
+/// The calculator XORs the seed with the mask of the ECU entry before handing it
+/// to the algorithm, so every algorithm works on the masked seed. Entries of a
+/// mask file select their algorithm by [`name`](SeedKeyAlgorithm::name) with
+/// the `algo=` attribute.
+pub trait SeedKeyAlgorithm: fmt::Debug + Send + Sync {
+    /// Name used in the `algo=` attribute of mask files
+    fn name(&self) -> &'static str;
+
+    /// Returns the accepted key lengths in bytes, empty if any non-empty key is accepted
+    fn key_lengths(&self, cipher: Option<CmacCipher>) -> Vec<usize>;
+
+    /// Returns the output length in bytes for a seed of `seed_len` bytes, or why
+    /// the algorithm cannot take such a seed
+    fn output_len(&self, seed_len: usize) -> Result<usize, String>;
+
+    /// Returns whether the algorithm is built on AES, so that a mask file entry
+    /// may fix its variant with the `cipher=` attribute
+    fn uses_cipher(&self) -> bool {
+        false
+    }
+
+    /// Checks the key length before calculation
+    fn check_key(&self, key: &[u8], cipher: Option<CmacCipher>) -> Result<(), Box<dyn Error>> {
+        let lengths = self.key_lengths(cipher);
+        if key.is_empty() || !(lengths.is_empty() || lengths.contains(&key.len())) {
+            let expected: Vec<String> = lengths.iter().map(|len| len.to_string()).collect();
+            return Err(format!(
+                "Invalid key length for {}: {} bytes, expected {}",
+                self.name(),
+                key.len(),
+                if expected.is_empty() { String::from("a non-empty key") } else { expected.join(", ") }
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Calculates the full length key response from the masked seed
+    fn calculate(&self, masked_seed: &[u8], key: &[u8], cipher: Option<CmacCipher>) -> Result<SecretBytes, Box<dyn Error>>;
+
+    /// Checks a recorded, possibly truncated key response against the leading
+    /// bytes of the calculated one in constant time
+    fn verify(&self, masked_seed: &[u8], key: &[u8], cipher: Option<CmacCipher>, response: &[u8]) -> bool {
+        self.calculate(masked_seed, key, cipher).is_ok_and(|output| {
+            !response.is_empty() && response.len() <= output.len() && constant_time_eq(&output[..response.len()], response)
+        })
+    }
+}
+
+/// AES-CMAC of the masked seed, the original seed2cmac scheme
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaskCmac;
+
+impl SeedKeyAlgorithm for MaskCmac {
+    fn name(&self) -> &'static str {
+        "cmac"
+    }
+
+    fn key_lengths(&self, cipher: Option<CmacCipher>) -> Vec<usize> {
+        aes_key_lengths(cipher)
+    }
+
+    fn output_len(&self, _seed_len: usize) -> Result<usize, String> {
+        Ok(CMAC_LEN)
+    }
+
+    fn uses_cipher(&self) -> bool {
+        true
+    }
+
+    fn calculate(&self, masked_seed: &[u8], key: &[u8], cipher: Option<CmacCipher>) -> Result<SecretBytes, Box<dyn Error>> {
+        calculate_cmac_with(select_cipher(cipher, key.len())?, key, masked_seed)
+    }
+}
+
+/// HMAC-SHA256 of the masked seed, keyed with a key of any length
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HmacSha256;
+
+impl SeedKeyAlgorithm for HmacSha256 {
+    fn name(&self) -> &'static str {
+        "hmac-sha256"
+    }
+
+    fn key_lengths(&self, _cipher: Option<CmacCipher>) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn output_len(&self, _seed_len: usize) -> Result<usize, String> {
+        Ok(32)
+    }
+
+    fn calculate(&self, masked_seed: &[u8], key: &[u8], _cipher: Option<CmacCipher>) -> Result<SecretBytes, Box<dyn Error>> {
+        self.check_key(key, None)?;
+        mac::<Hmac<Sha256>>(key, masked_seed)
+    }
+}
+
+/// AES encryption of the masked seed in ECB mode, the seed must be a multiple of
+/// the 16 byte block size
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AesEcb;
+
+impl SeedKeyAlgorithm for AesEcb {
+    fn name(&self) -> &'static str {
+        "aes-ecb"
+    }
+
+    fn key_lengths(&self, cipher: Option<CmacCipher>) -> Vec<usize> {
+        aes_key_lengths(cipher)
+    }
+
+    fn output_len(&self, seed_len: usize) -> Result<usize, String> {
+        if seed_len.is_multiple_of(16) {
+            Ok(seed_len)
+        } else {
+            Err(format!("algorithm aes-ecb needs a multiple of 16 seed bytes, found {}", seed_len))
+        }
+    }
+
+    fn uses_cipher(&self) -> bool {
+        true
+    }
+
+    fn calculate(&self, masked_seed: &[u8], key: &[u8], cipher: Option<CmacCipher>) -> Result<SecretBytes, Box<dyn Error>> {
+        if masked_seed.is_empty() || !masked_seed.len().is_multiple_of(16) {
+            return Err(format!("Seed length must be a multiple of 16 bytes, got {}", masked_seed.len()).into());
+        }
+
+        match select_cipher(cipher, key.len())? {
+            CmacCipher::Aes128 => ecb::<Aes128>(key, masked_seed),
+            CmacCipher::Aes192 => ecb::<Aes192>(key, masked_seed),
+            CmacCipher::Aes256 => ecb::<Aes256>(key, masked_seed),
+        }
+    }
+}
+
+fn ecb<C: BlockEncrypt + KeyInit>(key: &[u8], data: &[u8]) -> Result<SecretBytes, Box<dyn Error>> {
+    let cipher = C::new_from_slice(key).map_err(|e| format!("Invalid key length: {:?}", e))?;
+
+    let mut output = SecretBytes::from(data);
+    for block in output.chunks_exact_mut(16) {
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+    }
+
+    Ok(output)
+}
+
+/// Legacy 32-bit algorithm of older ECUs: the masked seed is shifted left 35
+/// times and XORed with the 4 byte key whenever the top bit is shifted out
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShiftXor32;
+
+impl ShiftXor32 {
+    const ROUNDS: usize = 35;
+}
+
+impl SeedKeyAlgorithm for ShiftXor32 {
+    fn name(&self) -> &'static str {
+        "shift-xor32"
+    }
+
+    fn key_lengths(&self, _cipher: Option<CmacCipher>) -> Vec<usize> {
+        vec![4]
+    }
+
+    fn output_len(&self, seed_len: usize) -> Result<usize, String> {
+        if seed_len == 4 {
+            Ok(4)
+        } else {
+            Err(format!("algorithm shift-xor32 needs a 4 byte seed, found {}", seed_len))
+        }
+    }
+
+    fn calculate(&self, masked_seed: &[u8], key: &[u8], _cipher: Option<CmacCipher>) -> Result<SecretBytes, Box<dyn Error>> {
+        self.check_key(key, None)?;
+        let seed: [u8; 4] = masked_seed
+            .try_into()
+            .map_err(|_| format!("Seed length must be 4 bytes, got {}", masked_seed.len()))?;
+        let key = u32::from_be_bytes(key.try_into().expect("checked key length"));
+
+        let mut value = u32::from_be_bytes(seed);
+        for _ in 0..Self::ROUNDS {
+            value = if value & 0x8000_0000 != 0 { (value << 1) ^ key } else { value << 1 };
+        }
+
+        Ok(SecretBytes::from(value.to_be_bytes().as_slice()))
+    }
+}
+
+fn aes_key_lengths(cipher: Option<CmacCipher>) -> Vec<usize> {
+    match cipher {
+        Some(cipher) => vec![cipher.key_len()],
+        None => CmacCipher::ALL.iter().map(|cipher| cipher.key_len()).collect(),
+    }
+}
+
+static ALGORITHMS: &[&dyn SeedKeyAlgorithm] = &[&MaskCmac, &HmacSha256, &AesEcb, &ShiftXor32];
+
+/// Returns the built-in algorithms, the ones mask files can name with `algo=`
+pub fn algorithms() -> &'static [&'static dyn SeedKeyAlgorithm] {
+    ALGORITHMS
+}
+
+/// Looks up a built-in algorithm by the name used in mask files
+pub fn algorithm(name: &str) -> Option<&'static dyn SeedKeyAlgorithm> {
+    ALGORITHMS.iter().copied().find(|algorithm| algorithm.name() == name)
+}
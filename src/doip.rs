@@ -0,0 +1,397 @@
+//! Diagnostics over IP (ISO 13400-2): vehicle identification, routing activation
+//! and diagnostic message framing, plus a loopback gateway for testing.
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use crate::client::UdsTransport;
+use crate::simulator::SimulatedEcu;
+
+/// UDP and TCP port of DoIP entities
+pub const DOIP_PORT: u16 = 13400;
+
+/// Logical address of the tester, from the range reserved for external test equipment
+pub const DEFAULT_TESTER_ADDRESS: u16 = 0x0E00;
+
+/// Protocol version ISO 13400-2:2012
+const PROTOCOL_VERSION: u8 = 0x02;
+
+const HEADER_LEN: usize = 8;
+
+/// Largest payload accepted, DoIP entities announce far smaller buffers in practice
+const MAX_PAYLOAD_LEN: usize = 0x1_0000;
+
+const GENERIC_NACK: u16 = 0x0000;
+const VEHICLE_IDENTIFICATION_REQUEST: u16 = 0x0001;
+const VEHICLE_IDENTIFICATION_RESPONSE: u16 = 0x0004;
+const ROUTING_ACTIVATION_REQUEST: u16 = 0x0005;
+const ROUTING_ACTIVATION_RESPONSE: u16 = 0x0006;
+const ALIVE_CHECK_REQUEST: u16 = 0x0007;
+const ALIVE_CHECK_RESPONSE: u16 = 0x0008;
+const DIAGNOSTIC_MESSAGE: u16 = 0x8001;
+const DIAGNOSTIC_ACK: u16 = 0x8002;
+const DIAGNOSTIC_NACK: u16 = 0x8003;
+
+/// Generic header NACK codes
+const UNKNOWN_PAYLOAD_TYPE: u8 = 0x01;
+const INVALID_PAYLOAD_LENGTH: u8 = 0x04;
+
+/// Routing activation response code of a successful activation
+const ROUTING_SUCCESSFUL: u8 = 0x10;
+
+/// Diagnostic message NACK codes
+const INVALID_SOURCE_ADDRESS: u8 = 0x02;
+const UNKNOWN_TARGET_ADDRESS: u8 = 0x03;
+
+/// Vehicle announcement or identification response of a DoIP entity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VehicleIdentification {
+    pub vin: String,
+    /// Logical address of the DoIP entity
+    pub logical_address: u16,
+    /// Entity identification, usually the MAC address
+    pub eid: [u8; 6],
+    /// Group identification
+    pub gid: [u8; 6],
+}
+
+impl VehicleIdentification {
+    fn to_payload(&self) -> Vec<u8> {
+        let mut vin = self.vin.as_bytes().to_vec();
+        vin.resize(17, 0);
+
+        let mut payload = vin;
+        payload.extend_from_slice(&self.logical_address.to_be_bytes());
+        payload.extend_from_slice(&self.eid);
+        payload.extend_from_slice(&self.gid);
+        // No further action required
+        payload.push(0x00);
+        payload
+    }
+
+    fn from_payload(payload: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if payload.len() < 32 {
+            return Err(format!("Invalid DoIP vehicle identification response: {:02X?}", payload).into());
+        }
+
+        let vin = String::from_utf8_lossy(&payload[..17]).trim_end_matches('\0').to_string();
+        Ok(Self {
+            vin,
+            logical_address: u16::from_be_bytes([payload[17], payload[18]]),
+            eid: payload[19..25].try_into().expect("6 bytes"),
+            gid: payload[25..31].try_into().expect("6 bytes"),
+        })
+    }
+}
+
+/// Returns a DoIP message of the payload type with the generic header
+pub fn encode(payload_type: u16, payload: &[u8]) -> Vec<u8> {
+    let mut message = vec![PROTOCOL_VERSION, !PROTOCOL_VERSION];
+    message.extend_from_slice(&payload_type.to_be_bytes());
+    message.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Splits a DoIP message into payload type and payload, checking the generic header
+pub fn decode(message: &[u8]) -> Result<(u16, &[u8]), Box<dyn Error>> {
+    let (payload_type, len) = decode_header(message.get(..HEADER_LEN).unwrap_or(message))?;
+    match message.get(HEADER_LEN..) {
+        Some(payload) if payload.len() == len => Ok((payload_type, payload)),
+        _ => Err(format!("DoIP message length does not match its header: {:02X?}", message).into()),
+    }
+}
+
+/// Returns payload type and payload length of a generic header
+fn decode_header(header: &[u8]) -> Result<(u16, usize), Box<dyn Error>> {
+    let [version, inverse, t0, t1, l0, l1, l2, l3] = header else {
+        return Err(format!("Truncated DoIP header: {:02X?}", header).into());
+    };
+    if *inverse != !*version {
+        return Err(format!("Invalid DoIP header: {:02X?}", header).into());
+    }
+
+    let len = u32::from_be_bytes([*l0, *l1, *l2, *l3]) as usize;
+    if len > MAX_PAYLOAD_LEN {
+        return Err(format!("DoIP payload of {} bytes exceeds {} bytes", len, MAX_PAYLOAD_LEN).into());
+    }
+    Ok((u16::from_be_bytes([*t0, *t1]), len))
+}
+
+/// Reads the next DoIP message of a TCP connection, keeping I/O errors as [`io::Error`]
+fn read_message(stream: &mut impl Read) -> Result<(u16, Vec<u8>), Box<dyn Error>> {
+    let mut header = [0; HEADER_LEN];
+    stream.read_exact(&mut header)?;
+    let (payload_type, len) = decode_header(&header)?;
+
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload)?;
+    Ok((payload_type, payload))
+}
+
+fn write_message(stream: &mut impl Write, payload_type: u16, payload: &[u8]) -> io::Result<()> {
+    stream.write_all(&encode(payload_type, payload))
+}
+
+/// Sends a vehicle identification request to `addr` over UDP, e.g. to
+/// `255.255.255.255:13400`, and returns the first response
+pub fn identify(addr: &str, timeout: Duration) -> Result<VehicleIdentification, Box<dyn Error>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_broadcast(true)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.send_to(&encode(VEHICLE_IDENTIFICATION_REQUEST, &[]), addr)?;
+
+    let mut buffer = [0; 512];
+    loop {
+        let (len, _) = socket.recv_from(&mut buffer)?;
+        if let Ok((VEHICLE_IDENTIFICATION_RESPONSE, payload)) = decode(&buffer[..len]) {
+            return VehicleIdentification::from_payload(payload);
+        }
+    }
+}
+
+/// Diagnostic messages to one ECU through a DoIP entity
+#[derive(Debug)]
+pub struct DoIpClient {
+    stream: TcpStream,
+    tester_address: u16,
+    ecu_address: u16,
+    timeout: Duration,
+}
+
+impl DoIpClient {
+    /// Connects to the DoIP entity at `addr` and activates routing, waiting up to
+    /// `timeout` for the connection and for each message of the entity but the
+    /// diagnostic responses, which are timed by the UDS client
+    pub fn connect(addr: &str, tester_address: u16, ecu_address: u16, timeout: Duration) -> Result<Self, Box<dyn Error>> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no address for {}", addr)))?;
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+
+        let mut client = Self { stream, tester_address, ecu_address, timeout };
+        client.activate_routing()?;
+        Ok(client)
+    }
+
+    /// Requests default routing activation for the tester address and returns
+    /// the logical address of the entity
+    pub fn activate_routing(&mut self) -> Result<u16, Box<dyn Error>> {
+        let mut request = self.tester_address.to_be_bytes().to_vec();
+        // Default activation type, reserved bytes
+        request.extend_from_slice(&[0x00, 0, 0, 0, 0]);
+        write_message(&mut self.stream, ROUTING_ACTIVATION_REQUEST, &request)?;
+
+        let payload = self.wait_for(ROUTING_ACTIVATION_RESPONSE)?;
+        let [_, _, e0, e1, code, ..] = payload[..] else {
+            return Err(format!("Invalid DoIP routing activation response: {:02X?}", payload).into());
+        };
+        if code != ROUTING_SUCCESSFUL {
+            return Err(format!("DoIP routing activation denied: 0x{:02X} ({})", code, routing_code_name(code)).into());
+        }
+        Ok(u16::from_be_bytes([e0, e1]))
+    }
+
+    /// Waits for a message of the payload type, answering alive checks meanwhile
+    fn wait_for(&mut self, payload_type: u16) -> Result<Vec<u8>, Box<dyn Error>> {
+        loop {
+            match read_message(&mut self.stream)? {
+                (t, payload) if t == payload_type => return Ok(payload),
+                (ALIVE_CHECK_REQUEST, _) => {
+                    write_message(&mut self.stream, ALIVE_CHECK_RESPONSE, &self.tester_address.to_be_bytes())?;
+                }
+                (GENERIC_NACK, payload) => {
+                    return Err(format!("DoIP entity rejected the message: generic NACK {:02X?}", payload).into());
+                }
+                (DIAGNOSTIC_NACK, payload) => {
+                    let code = payload.get(4).copied().unwrap_or(0);
+                    return Err(format!("DoIP diagnostic message rejected: 0x{:02X} ({})", code, diagnostic_nack_name(code)).into());
+                }
+                // E.g. a late acknowledgement
+                _ => continue,
+            }
+        }
+    }
+}
+
+impl UdsTransport for DoIpClient {
+    /// Sends a diagnostic message and waits for its acknowledgement
+    fn send(&mut self, message: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut payload = self.tester_address.to_be_bytes().to_vec();
+        payload.extend_from_slice(&self.ecu_address.to_be_bytes());
+        payload.extend_from_slice(message);
+        write_message(&mut self.stream, DIAGNOSTIC_MESSAGE, &payload)?;
+        self.stream.set_read_timeout(Some(self.timeout))?;
+        self.wait_for(DIAGNOSTIC_ACK)?;
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        loop {
+            let payload = self.wait_for(DIAGNOSTIC_MESSAGE)?;
+            if payload.len() > 4 && payload[..2] == self.ecu_address.to_be_bytes() {
+                return Ok(payload[4..].to_vec());
+            }
+        }
+    }
+}
+
+/// Answers vehicle identification requests on the UDP socket until it fails
+pub fn serve_identification(socket: &UdpSocket, identification: &VehicleIdentification) -> io::Result<()> {
+    let response = encode(VEHICLE_IDENTIFICATION_RESPONSE, &identification.to_payload());
+    let mut buffer = [0; 512];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buffer)?;
+        if let Ok((VEHICLE_IDENTIFICATION_REQUEST, _)) = decode(&buffer[..len]) {
+            socket.send_to(&response, peer)?;
+        }
+    }
+}
+
+/// Parses a logical address in hex (`0x0E00`) or decimal notation
+pub fn parse_logical_address(text: &str) -> Result<u16, String> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse::<u16>(),
+    }
+    .map_err(|_| format!("invalid logical address `{}`", text))
+}
+
+/// Appends the DoIP port to an address without one, e.g. `192.168.0.10`
+pub fn with_default_port(addr: &str) -> String {
+    if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{}:{}", addr, DOIP_PORT)
+    }
+}
+
+/// Returns the ISO 13400-2 meaning of a routing activation response code
+pub fn routing_code_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "unknown source address",
+        0x01 => "all sockets registered and active",
+        0x02 => "source address differs from the one registered to the socket",
+        0x03 => "source address already registered on another socket",
+        0x04 => "missing authentication",
+        0x05 => "rejected confirmation",
+        0x06 => "unsupported routing activation type",
+        0x10 => "routing successfully activated",
+        0x11 => "confirmation required",
+        _ => "unknown",
+    }
+}
+
+/// Returns the ISO 13400-2 meaning of a diagnostic message NACK code
+pub fn diagnostic_nack_name(code: u8) -> &'static str {
+    match code {
+        0x02 => "invalid source address",
+        0x03 => "unknown target address",
+        0x04 => "diagnostic message too large",
+        0x05 => "out of memory",
+        0x06 => "target unreachable",
+        0x07 => "unknown network",
+        0x08 => "transport protocol error",
+        _ => "unknown",
+    }
+}
+
+/// Callback receiving the ECU address, request and response of a routed diagnostic message
+pub type GatewayLog<'a> = dyn FnMut(u16, &[u8], &[u8]) + 'a;
+
+/// Loopback stand-in for a DoIP gateway routing diagnostic messages to simulated ECUs
+#[derive(Debug)]
+pub struct DoIpGateway {
+    identification: VehicleIdentification,
+    ecus: Vec<(u16, SimulatedEcu)>,
+}
+
+impl DoIpGateway {
+    pub fn new(identification: VehicleIdentification) -> Self {
+        Self { identification, ecus: Vec::new() }
+    }
+
+    pub fn identification(&self) -> &VehicleIdentification {
+        &self.identification
+    }
+
+    /// Routes diagnostic messages to `address` to the simulated ECU
+    pub fn add_ecu(&mut self, address: u16, ecu: SimulatedEcu) {
+        self.ecus.retain(|(a, _)| *a != address);
+        self.ecus.push((address, ecu));
+    }
+
+    /// Accepts TCP connections one after another and serves each of them, calling
+    /// `log` with the ECU address, request and response of every diagnostic message
+    pub fn serve_tcp(&mut self, listener: &TcpListener, log: &mut GatewayLog) -> Result<(), Box<dyn Error>> {
+        for stream in listener.incoming() {
+            let mut stream = stream?;
+            // Lock the ECUs again for each tester, like a power cycle
+            for (_, ecu) in &mut self.ecus {
+                ecu.handle(&[0x10, 0x01]);
+            }
+            // A misbehaving tester only ends its own connection
+            let _ = self.serve(&mut stream, log);
+        }
+        Ok(())
+    }
+
+    /// Serves one tester until it disconnects
+    fn serve(&mut self, stream: &mut TcpStream, log: &mut GatewayLog) -> Result<(), Box<dyn Error>> {
+        let mut tester = None;
+        loop {
+            let (payload_type, payload) = match read_message(stream) {
+                Ok(message) => message,
+                Err(e) if e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::UnexpectedEof) => {
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            };
+
+            match payload_type {
+                ROUTING_ACTIVATION_REQUEST if payload.len() >= 7 => {
+                    let source = u16::from_be_bytes([payload[0], payload[1]]);
+                    tester = Some(source);
+                    let mut response = source.to_be_bytes().to_vec();
+                    response.extend_from_slice(&self.identification.logical_address.to_be_bytes());
+                    response.extend_from_slice(&[ROUTING_SUCCESSFUL, 0, 0, 0, 0]);
+                    write_message(stream, ROUTING_ACTIVATION_RESPONSE, &response)?;
+                }
+                DIAGNOSTIC_MESSAGE if payload.len() > 4 => {
+                    let source = u16::from_be_bytes([payload[0], payload[1]]);
+                    let target = u16::from_be_bytes([payload[2], payload[3]]);
+                    let addresses = [&payload[2..4], &payload[..2]].concat();
+
+                    let ecu = self.ecus.iter_mut().find(|(a, _)| *a == target).map(|(_, ecu)| ecu);
+                    let nack = match ecu {
+                        _ if tester != Some(source) => Some(INVALID_SOURCE_ADDRESS),
+                        None => Some(UNKNOWN_TARGET_ADDRESS),
+                        Some(_) => None,
+                    };
+                    if let Some(code) = nack {
+                        write_message(stream, DIAGNOSTIC_NACK, &[&addresses[..], &[code]].concat())?;
+                        continue;
+                    }
+                    write_message(stream, DIAGNOSTIC_ACK, &[&addresses[..], &[0x00]].concat())?;
+
+                    let ecu = ecu.expect("checked above");
+                    let response = ecu.handle(&payload[4..]);
+                    log(target, &payload[4..], &response);
+                    if !response.is_empty() {
+                        write_message(stream, DIAGNOSTIC_MESSAGE, &[&addresses[..], &response[..]].concat())?;
+                    }
+                }
+                ALIVE_CHECK_RESPONSE => {}
+                ROUTING_ACTIVATION_REQUEST | DIAGNOSTIC_MESSAGE => {
+                    write_message(stream, GENERIC_NACK, &[INVALID_PAYLOAD_LENGTH])?
+                }
+                _ => write_message(stream, GENERIC_NACK, &[UNKNOWN_PAYLOAD_TYPE])?,
+            }
+        }
+    }
+}
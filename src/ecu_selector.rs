@@ -0,0 +1,178 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use iced::widget::combo_box;
+
+use seed2cmac::{EcuType, MaskTable};
+
+/// Number of recently used ECUs shown at the top of the picker
+const MAX_RECENT: usize = 5;
+
+/// Entry of the ECU picker, an ECU labelled with its section, e.g. `最近使用 · DKM1_L`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EcuChoice {
+    pub group: String,
+    pub ecu: EcuType,
+}
+
+impl fmt::Display for EcuChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} · {}", self.group, self.ecu)
+    }
+}
+
+/// Recently used ECUs and the filterable picker listing them
+#[derive(Debug)]
+pub struct EcuSelector {
+    recent: Vec<EcuType>,
+    state: combo_box::State<EcuChoice>,
+    /// File the recently used ECUs are saved to
+    file: Option<PathBuf>,
+}
+
+impl EcuSelector {
+    /// Creates a selector for the ECUs of `table` with the recently used ECUs
+    /// of the last session
+    pub fn load(table: &MaskTable) -> Self {
+        Self::load_from(table, recent_file())
+    }
+
+    fn load_from(table: &MaskTable, file: Option<PathBuf>) -> Self {
+        // An unreadable file starts an empty list, blank and repeated lines are skipped
+        let mut recent: Vec<EcuType> = Vec::new();
+        let content = file.as_deref().and_then(|file| fs::read_to_string(file).ok()).unwrap_or_default();
+        for ecu in content.lines().map(str::trim).filter(|line| !line.is_empty()).map(EcuType::new) {
+            if recent.len() < MAX_RECENT && !recent.contains(&ecu) {
+                recent.push(ecu);
+            }
+        }
+
+        let state = combo_box::State::new(choices(&recent, table));
+        Self { recent, state, file }
+    }
+
+    /// Returns the picker state, which filters the entries while typing
+    pub fn state(&self) -> &combo_box::State<EcuChoice> {
+        &self.state
+    }
+
+    /// Returns the entry shown for the selected ECU, the one in its own domain
+    /// rather than among the recently used
+    pub fn selection(&self, ecu: &EcuType) -> Option<&EcuChoice> {
+        self.state.options().iter().rev().find(|choice| choice.ecu == *ecu)
+    }
+
+    /// Moves the ECU to the top of the recently used list and saves the list
+    pub fn record_use(&mut self, ecu: &EcuType, table: &MaskTable) {
+        if self.recent.first() == Some(ecu) {
+            return;
+        }
+        self.recent.retain(|recent| recent != ecu);
+        self.recent.insert(0, ecu.clone());
+        self.recent.truncate(MAX_RECENT);
+        self.state = combo_box::State::new(choices(&self.recent, table));
+
+        // The list is only a convenience, failing to save it is not worth an error dialog
+        if let Some(file) = &self.file {
+            let content: Vec<&str> = self.recent.iter().map(EcuType::as_str).collect();
+            let _ = file
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(file, content.join("\n")));
+        }
+    }
+}
+
+/// Returns the picker entries: the recently used ECUs followed by all ECUs
+/// grouped by domain
+fn choices(recent: &[EcuType], table: &MaskTable) -> Vec<EcuChoice> {
+    let ecu_types = table.ecu_types();
+    let recent = recent
+        .iter()
+        .filter(|ecu| ecu_types.contains(ecu))
+        .map(|ecu| EcuChoice { group: String::from("最近使用"), ecu: ecu.clone() });
+
+    let grouped = table.ecu_groups().into_iter().flat_map(|(group, ecus)| {
+        let group = group.unwrap_or_else(|| String::from("其他"));
+        ecus.into_iter().map(move |ecu| EcuChoice { group: group.clone(), ecu })
+    });
+
+    recent.chain(grouped).collect()
+}
+
+/// File storing the recently used ECUs between sessions
+fn recent_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("seed2cmac").join("recent_ecus.txt"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = "\
+[动力]
+BMS 1 = 00000000000000000000000000000000
+VCU 1 = 00000000000000000000000000000000
+[车身]
+DKM1_L 1 = 00000000000000000000000000000000
+GW 1 = 00000000000000000000000000000000
+GW 3 = 00000000000000000000000000000000
+";
+
+    fn labels(selector: &EcuSelector) -> Vec<String> {
+        selector.state().options().iter().map(ToString::to_string).collect()
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("seed2cmac-recent-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn entries_are_labelled_with_their_section() {
+        let table = MaskTable::parse(&format!("TOOL 1 = 00000000000000000000000000000000\n{}", TABLE)).unwrap();
+        let selector = EcuSelector::load_from(&table, None);
+        assert_eq!(labels(&selector), ["动力 · BMS", "动力 · VCU", "车身 · DKM1_L", "车身 · GW", "其他 · TOOL"]);
+        assert_eq!(selector.selection(&EcuType::new("GW")).map(ToString::to_string).as_deref(), Some("车身 · GW"));
+    }
+
+    #[test]
+    fn recently_used_ecus_come_first_without_duplicates() {
+        let table = MaskTable::parse(TABLE).unwrap();
+        let file = temp_file("order").join("recent_ecus.txt");
+        let mut selector = EcuSelector::load_from(&table, Some(file.clone()));
+        for ecu in ["GW", "BMS", "GW", "GW"] {
+            selector.record_use(&EcuType::new(ecu), &table);
+        }
+        assert_eq!(labels(&selector)[..2], ["最近使用 · GW", "最近使用 · BMS"]);
+        // The selection stays the entry in the ECU's own domain
+        assert_eq!(selector.selection(&EcuType::new("GW")).map(ToString::to_string).as_deref(), Some("车身 · GW"));
+
+        for ecu in ["VCU", "DKM1_L", "BMS", "GW", "VCU", "DKM1_L"] {
+            selector.record_use(&EcuType::new(ecu), &table);
+        }
+        let reloaded = EcuSelector::load_from(&table, Some(file.clone()));
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+        assert_eq!(reloaded.recent, ["DKM1_L", "VCU", "GW", "BMS"].map(EcuType::new));
+        assert_eq!(labels(&reloaded).len(), 4 + 4);
+    }
+
+    #[test]
+    fn missing_or_corrupt_recent_files_are_ignored() {
+        let table = MaskTable::parse(TABLE).unwrap();
+        let missing = EcuSelector::load_from(&table, Some(temp_file("missing.txt")));
+        assert!(missing.recent.is_empty());
+        assert_eq!(labels(&missing).len(), 4);
+
+        let file = temp_file("corrupt.txt");
+        fs::write(&file, b"GW\n\n  \nREMOVED\nGW\nBMS\n").unwrap();
+        let corrupt = EcuSelector::load_from(&table, Some(file.clone()));
+        assert_eq!(labels(&corrupt)[..2], ["最近使用 · GW", "最近使用 · BMS"]);
+        assert_eq!(labels(&corrupt).len(), 2 + 4);
+
+        fs::write(&file, [0xff, 0xfe, 0x00, 0x47]).unwrap();
+        let binary = EcuSelector::load_from(&table, Some(file.clone()));
+        fs::remove_file(&file).unwrap();
+        assert!(binary.recent.is_empty());
+    }
+}
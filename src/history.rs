@@ -0,0 +1,227 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use seed2cmac::audit::utc_timestamp;
+use seed2cmac::keystore::is_reference;
+use seed2cmac::util::SecretString;
+
+/// Number of calculations kept, older ones are dropped
+const MAX_ENTRIES: usize = 200;
+
+/// One calculation of the session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// UTC time of the calculation, e.g. `2024-05-01T08:30:00Z`
+    pub timestamp: String,
+    pub ecu: String,
+    pub level: String,
+    pub seed: String,
+    /// The `@alias` of the key, or `****` for a key typed in
+    pub key: String,
+    pub result: SecretString,
+}
+
+impl HistoryEntry {
+    pub fn new(ecu: &str, level: &str, seed: &str, key: &str, result: SecretString) -> Self {
+        Self {
+            timestamp: utc_timestamp(SystemTime::now()),
+            ecu: ecu.to_string(),
+            level: level.to_string(),
+            seed: seed.to_string(),
+            key: mask_key(key),
+            result,
+        }
+    }
+
+    /// Returns the time of day of the calculation, e.g. `08:30:00`
+    pub fn time(&self) -> &str {
+        self.timestamp.get(11..19).unwrap_or(&self.timestamp)
+    }
+}
+
+/// Calculations of the session, oldest first
+///
+/// The history is kept across restarts only while its file exists in the data
+/// directory, so turning persistence on or off needs no separate setting.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    persistent: bool,
+}
+
+impl History {
+    /// Creates the history with the entries of earlier sessions if it is persistent
+    pub fn load() -> Self {
+        history_file().map(|file| Self::load_from(&file)).unwrap_or_default()
+    }
+
+    fn load_from(file: &Path) -> Self {
+        let Ok(content) = fs::read_to_string(file) else {
+            return Self::default();
+        };
+        // A damaged line only loses its own entry
+        let entries = content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+        Self { entries, persistent: true }
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.persistent
+    }
+
+    /// Appends the entry, dropping the oldest beyond the limit, and saves the
+    /// history if it is persistent
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.entries.push(entry);
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        self.entries.drain(..excess);
+
+        // Like the recently used ECUs, failing to save is not worth an error dialog
+        if self.persistent {
+            let _ = self.save();
+        }
+    }
+
+    /// Saves the history to the data directory and keeps it saved, or deletes
+    /// the saved history
+    pub fn set_persistent(&mut self, persistent: bool) -> Result<(), Box<dyn Error>> {
+        if persistent {
+            self.save()?;
+        } else if let Some(file) = history_file().filter(|file| file.exists()) {
+            fs::remove_file(file)?;
+        }
+        self.persistent = persistent;
+        Ok(())
+    }
+
+    /// Writes the history with `timestamp,ecu,level,seed,key,result` columns
+    pub fn export_csv(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_path(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        for entry in &self.entries {
+            writer.serialize(entry)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        self.save_to(&history_file().ok_or("No data directory")?)
+    }
+
+    fn save_to(&self, file: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut lines = String::new();
+        for entry in &self.entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+        fs::write(file, lines)?;
+        Ok(())
+    }
+}
+
+/// Returns the file a CSV export is written to, in the downloads or home directory
+pub fn export_path() -> Option<PathBuf> {
+    let timestamp = utc_timestamp(SystemTime::now()).replace(':', "-");
+    dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .map(|dir| dir.join(format!("seed2cmac-history-{}.csv", timestamp)))
+}
+
+/// Keeps an `@alias` and replaces a raw key, none of whose digits are ever stored
+fn mask_key(key: &str) -> String {
+    if is_reference(key) {
+        key.to_string()
+    } else {
+        String::from("****")
+    }
+}
+
+/// File storing the history between sessions
+fn history_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("seed2cmac").join("history.jsonl"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(seed: &str, key: &str) -> HistoryEntry {
+        HistoryEntry::new("BMS", "0x01", seed, key, SecretString::from("a1b2c3d4"))
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("seed2cmac-history-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn typed_keys_are_masked_and_aliases_kept() {
+        assert_eq!(entry("00", "2b7e151628aed2a6abf7158809cf4f3c").key, "****");
+        assert_eq!(entry("00", "").key, "****");
+        assert_eq!(entry("00", "@BMS/P1/prod").key, "@BMS/P1/prod");
+    }
+
+    #[test]
+    fn export_columns_and_escaping() {
+        let mut history = History::default();
+        history.record(entry("00112233", "@BMS/P1/prod"));
+        history.record(HistoryEntry::new("GW, \"front\"", "0x11", "aabb", "00112233", SecretString::from("27 12 A1")));
+
+        let file = temp_file("export.csv");
+        history.export_csv(&file).unwrap();
+        let csv = fs::read_to_string(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "timestamp,ecu,level,seed,key,result");
+        assert!(lines[1].ends_with(",BMS,0x01,00112233,@BMS/P1/prod,a1b2c3d4"));
+        assert!(lines[2].ends_with(",\"GW, \"\"front\"\"\",0x11,aabb,****,27 12 A1"));
+        assert!(!csv.contains("00112233,****"));
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped_beyond_the_limit() {
+        let mut history = History::default();
+        for index in 0..MAX_ENTRIES + 5 {
+            history.record(entry(&format!("{:04x}", index), ""));
+        }
+        assert_eq!(history.entries().len(), MAX_ENTRIES);
+        assert_eq!(history.entries()[0].seed, "0005");
+        assert_eq!(history.entries()[MAX_ENTRIES - 1].seed, format!("{:04x}", MAX_ENTRIES + 4));
+    }
+
+    #[test]
+    fn saved_history_loads_again() {
+        let mut history = History::default();
+        history.record(entry("00112233", "@BMS/P1/prod"));
+        history.record(entry("44556677", "2b7e151628aed2a6abf7158809cf4f3c"));
+
+        let file = temp_file("save").join("history.jsonl");
+        history.save_to(&file).unwrap();
+        // A damaged line only loses its own entry
+        let content = fs::read_to_string(&file).unwrap();
+        fs::write(&file, format!("not json\n{}", content)).unwrap();
+        let loaded = History::load_from(&file);
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+
+        assert!(loaded.is_persistent());
+        assert_eq!(loaded.entries().len(), 2);
+        for (loaded, saved) in loaded.entries().iter().zip(history.entries()) {
+            assert_eq!(
+                (&loaded.timestamp, &loaded.ecu, &loaded.level, &loaded.seed, &loaded.key, &loaded.result),
+                (&saved.timestamp, &saved.ecu, &saved.level, &saved.seed, &saved.key, &saved.result)
+            );
+        }
+        assert!(!content.contains("2b7e1516"));
+        assert!(!History::load_from(&temp_file("missing.jsonl")).is_persistent());
+    }
+}
@@ -0,0 +1,231 @@
+//! ISO 15765-2 (ISO-TP) transport over classic CAN with normal addressing.
+
+use std::error::Error;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::can::{CanFrame, CanInterface};
+
+/// Largest payload of an ISO-TP message on classic CAN
+pub const MAX_PAYLOAD_LEN: usize = 4095;
+
+const SINGLE_FRAME: u8 = 0x00;
+const FIRST_FRAME: u8 = 0x10;
+const CONSECUTIVE_FRAME: u8 = 0x20;
+const FLOW_CONTROL: u8 = 0x30;
+
+const CONTINUE_TO_SEND: u8 = 0x00;
+const WAIT: u8 = 0x01;
+const OVERFLOW: u8 = 0x02;
+
+/// Identifiers and timing of an ISO-TP connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsoTpConfig {
+    /// CAN identifier of the frames we send, e.g. 0x7E0 for a physical request
+    pub tx_id: u32,
+    /// CAN identifier of the frames we receive, e.g. 0x7E8
+    pub rx_id: u32,
+    /// Time to wait for the next frame of the peer within a message (N_Bs, N_Cr)
+    pub timeout: Duration,
+    /// Byte used to pad frames to 8 bytes, `None` to send frames of minimal length
+    pub padding: Option<u8>,
+    /// Number of flow control WAIT frames accepted in a row before sending fails (N_WFTmax)
+    pub max_wait_frames: usize,
+}
+
+impl IsoTpConfig {
+    /// Creates a configuration with a timeout of one second, 0xCC padding and up to
+    /// 10 flow control WAIT frames in a row
+    pub fn new(tx_id: u32, rx_id: u32) -> Self {
+        Self { tx_id, rx_id, timeout: Duration::from_secs(1), padding: Some(0xCC), max_wait_frames: 10 }
+    }
+}
+
+/// ISO-TP connection exchanging messages of up to 4095 bytes between a pair of CAN identifiers
+#[derive(Debug)]
+pub struct IsoTp<C: CanInterface> {
+    can: C,
+    config: IsoTpConfig,
+}
+
+impl<C: CanInterface> IsoTp<C> {
+    pub fn new(can: C, config: IsoTpConfig) -> Self {
+        Self { can, config }
+    }
+
+    pub fn config(&self) -> &IsoTpConfig {
+        &self.config
+    }
+
+    /// Sends a message, segmenting it into first and consecutive frames if it
+    /// does not fit into a single frame
+    pub fn send(&mut self, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+        if payload.is_empty() || payload.len() > MAX_PAYLOAD_LEN {
+            return Err(format!("ISO-TP payload must be 1 to {} bytes, got {}", MAX_PAYLOAD_LEN, payload.len()).into());
+        }
+
+        if payload.len() <= 7 {
+            let mut data = vec![SINGLE_FRAME | payload.len() as u8];
+            data.extend_from_slice(payload);
+            return self.send_frame(data);
+        }
+
+        let mut data = vec![FIRST_FRAME | (payload.len() >> 8) as u8, payload.len() as u8];
+        data.extend_from_slice(&payload[..6]);
+        self.send_frame(data)?;
+
+        let mut sequence = 1u8;
+        let mut chunks = payload[6..].chunks(7).peekable();
+        while chunks.peek().is_some() {
+            let (block_size, separation_time) = self.wait_flow_control()?;
+
+            let mut sent = 0;
+            while let Some(chunk) = chunks.next() {
+                let mut data = vec![CONSECUTIVE_FRAME | sequence];
+                data.extend_from_slice(chunk);
+                self.send_frame(data)?;
+                sequence = (sequence + 1) & 0x0F;
+
+                sent += 1;
+                if block_size != 0 && sent == block_size {
+                    break;
+                }
+                if chunks.peek().is_some() {
+                    thread::sleep(separation_time);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Receives the next message, sending flow control for segmented messages.
+    /// Fails with [`io::ErrorKind::TimedOut`] if no frame arrives in time.
+    pub fn receive(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.receive_within(self.config.timeout)
+    }
+
+    /// Receives the next message like [`IsoTp::receive`], waiting up to `timeout`
+    /// for its first frame, e.g. the P2 time of a UDS response
+    pub fn receive_within(&mut self, timeout: Duration) -> Result<Vec<u8>, Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let data = self.receive_frame(deadline.saturating_duration_since(Instant::now()))?;
+            match data.first().map(|pci| pci & 0xF0) {
+                Some(SINGLE_FRAME) => {
+                    let len = (data[0] & 0x0F) as usize;
+                    if len == 0 || len >= data.len() {
+                        return Err(format!("Invalid ISO-TP single frame: {:02X?}", data).into());
+                    }
+                    return Ok(data[1..=len].to_vec());
+                }
+                Some(FIRST_FRAME) => return self.receive_segmented(&data),
+                // Frames of an aborted transfer or flow control for someone else
+                _ => continue,
+            }
+        }
+    }
+
+    fn receive_segmented(&mut self, first: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        if first.len() < 8 {
+            return Err(format!("Invalid ISO-TP first frame: {:02X?}", first).into());
+        }
+        let len = (((first[0] & 0x0F) as usize) << 8) | first[1] as usize;
+        if len <= 7 {
+            return Err(format!("Invalid ISO-TP message length {} in first frame", len).into());
+        }
+
+        let mut payload = first[2..].to_vec();
+        // Continue to send, no block size limit, no separation time
+        self.send_frame(vec![FLOW_CONTROL | CONTINUE_TO_SEND, 0, 0])?;
+
+        let mut sequence = 1u8;
+        while payload.len() < len {
+            let data = self.receive_frame(self.config.timeout)?;
+            match data.first() {
+                Some(pci) if pci & 0xF0 == CONSECUTIVE_FRAME => {
+                    if pci & 0x0F != sequence {
+                        return Err(format!(
+                            "ISO-TP sequence error: expected {}, got {}",
+                            sequence,
+                            pci & 0x0F
+                        )
+                        .into());
+                    }
+                    let remaining = len - payload.len();
+                    payload.extend_from_slice(&data[1..data.len().min(remaining + 1)]);
+                    sequence = (sequence + 1) & 0x0F;
+                }
+                _ => return Err(format!("Unexpected ISO-TP frame during reception: {:02X?}", data).into()),
+            }
+        }
+
+        Ok(payload)
+    }
+
+    /// Waits for a flow control frame, returning block size and separation time
+    fn wait_flow_control(&mut self) -> Result<(usize, Duration), Box<dyn Error>> {
+        let mut wait_frames = 0;
+        loop {
+            let data = self.receive_frame(self.config.timeout)?;
+            let (Some(pci), Some(block_size), Some(separation_time)) = (data.first(), data.get(1), data.get(2)) else {
+                continue;
+            };
+            if pci & 0xF0 != FLOW_CONTROL {
+                continue;
+            }
+
+            match pci & 0x0F {
+                CONTINUE_TO_SEND => return Ok((*block_size as usize, separation_time_of(*separation_time))),
+                WAIT if wait_frames < self.config.max_wait_frames => wait_frames += 1,
+                WAIT => {
+                    return Err(format!("ISO-TP receiver sent more than {} flow control WAIT frames", self.config.max_wait_frames).into());
+                }
+                OVERFLOW => return Err("ISO-TP receiver reported buffer overflow".into()),
+                status => return Err(format!("Invalid ISO-TP flow status {}", status).into()),
+            }
+        }
+    }
+
+    fn send_frame(&mut self, mut data: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        if let Some(padding) = self.config.padding {
+            data.resize(8, padding);
+        }
+        self.can.send(&CanFrame { id: self.config.tx_id, data })?;
+        Ok(())
+    }
+
+    /// Receives the data of the next frame from the peer within `timeout`, ignoring other identifiers
+    fn receive_frame(&mut self, timeout: Duration) -> Result<Vec<u8>, Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.can.recv(remaining)? {
+                Some(frame) if frame.id == self.config.rx_id && !frame.data.is_empty() => return Ok(frame.data),
+                Some(_) if !remaining.is_zero() => continue,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!(
+                            "Timeout waiting for CAN frame 0x{:X} after {} ms",
+                            self.config.rx_id,
+                            timeout.as_millis()
+                        ),
+                    )
+                    .into());
+                }
+            }
+        }
+    }
+}
+
+/// Decodes the STmin byte of a flow control frame
+fn separation_time_of(st_min: u8) -> Duration {
+    match st_min {
+        0x00..=0x7F => Duration::from_millis(st_min as u64),
+        0xF1..=0xF9 => Duration::from_micros((st_min - 0xF0) as u64 * 100),
+        // Reserved values are treated as the maximum
+        _ => Duration::from_millis(0x7F),
+    }
+}
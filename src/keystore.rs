@@ -0,0 +1,249 @@
+//! Named secret keys encrypted at rest with a passphrase.
+//!
+//! The store file is JSON holding the Argon2id parameters and salt, the AES-256-GCM
+//! nonce and the encrypted alias to key map. Keys are referenced as `@alias`
+//! wherever a hex key is accepted, so technicians never handle raw keys.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::mask::config_dir;
+use crate::util::{bytes_to_secret_hex, hex_string_to_bytes, parse_hex_input, SecretBytes, SecretString};
+
+/// File name of the key store in the config directory
+pub const KEY_STORE_FILE: &str = "keys.json";
+
+const VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Argon2id parameters accepted from a store file: no weaker than the defaults
+/// the store is written with, and bounded so that an edited file cannot make
+/// unlocking take minutes or gigabytes
+const M_COST: RangeInclusive<u32> = Params::DEFAULT_M_COST..=1024 * 1024;
+const T_COST: RangeInclusive<u32> = Params::DEFAULT_T_COST..=10;
+const P_COST: RangeInclusive<u32> = Params::DEFAULT_P_COST..=16;
+
+/// Encrypted key store file
+#[derive(Debug, Serialize, Deserialize)]
+struct StoreFile {
+    version: u32,
+    kdf: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Decrypted content of the store file
+#[derive(Serialize, Deserialize)]
+struct StoreContent {
+    keys: BTreeMap<String, SecretString>,
+}
+
+/// Secret keys by alias, e.g. `BMS/P1/prod`
+#[derive(Clone, Default)]
+pub struct KeyStore {
+    keys: BTreeMap<String, SecretBytes>,
+}
+
+/// Only the aliases are shown, never the keys
+impl fmt::Debug for KeyStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyStore").field("aliases", &self.aliases()).finish()
+    }
+}
+
+impl KeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `keys.json` in the seed2cmac config directory
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(KEY_STORE_FILE))
+    }
+
+    /// Opens and decrypts the store file
+    pub fn open(path: &Path, passphrase: &str) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::decrypt(&contents, passphrase)
+    }
+
+    /// Opens the store file, or returns an empty store if it does not exist yet
+    pub fn open_or_new(path: &Path, passphrase: &str) -> Result<Self, Box<dyn Error>> {
+        if path.exists() {
+            Self::open(path, passphrase)
+        } else {
+            Ok(Self::new())
+        }
+    }
+
+    /// Encrypts the store with a fresh salt and nonce and writes it to the file.
+    ///
+    /// The contents are written to a sibling temporary file readable only by the
+    /// owner, which then replaces the file, so a failed save keeps the old store.
+    pub fn save(&self, path: &Path, passphrase: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let contents = self.encrypt(passphrase)?;
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let result = write_private(&temp, contents.as_bytes()).and_then(|_| fs::rename(&temp, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result.map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Returns the contents of the store file
+    pub fn encrypt(&self, passphrase: &str) -> Result<String, Box<dyn Error>> {
+        let params = Params::default();
+        let mut salt = [0; SALT_LEN];
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let content = StoreContent { keys: self.keys.iter().map(|(alias, key)| (alias.clone(), bytes_to_secret_hex(key))).collect() };
+        let cipher = cipher(passphrase, &salt, &params)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), SecretBytes::new(serde_json::to_vec(&content)?).as_slice())
+            .map_err(|_| "Key store encryption failed")?;
+
+        let file = StoreFile {
+            version: VERSION,
+            kdf: String::from("argon2id"),
+            m_cost: params.m_cost(),
+            t_cost: params.t_cost(),
+            p_cost: params.p_cost(),
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        };
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    /// Decrypts the contents of a store file
+    pub fn decrypt(contents: &str, passphrase: &str) -> Result<Self, Box<dyn Error>> {
+        let file: StoreFile = serde_json::from_str(contents).map_err(|e| format!("Invalid key store: {}", e))?;
+        if file.version != VERSION || file.kdf != "argon2id" {
+            return Err(format!("Unsupported key store version {} with {}", file.version, file.kdf).into());
+        }
+
+        for (name, value, range) in [("m_cost", file.m_cost, M_COST), ("t_cost", file.t_cost, T_COST), ("p_cost", file.p_cost, P_COST)] {
+            if !range.contains(&value) {
+                return Err(format!("Invalid key store: Argon2 {} {} outside {}..={}", name, value, range.start(), range.end()).into());
+            }
+        }
+        let params = Params::new(file.m_cost, file.t_cost, file.p_cost, Some(32)).map_err(|e| format!("Invalid key store: {}", e))?;
+        let salt = hex_string_to_bytes(&file.salt).ok().filter(|salt| salt.len() >= SALT_LEN).ok_or("Invalid key store salt")?;
+        let nonce = hex_string_to_bytes(&file.nonce).ok().filter(|nonce| nonce.len() == NONCE_LEN).ok_or("Invalid key store nonce")?;
+        let ciphertext = hex_string_to_bytes(&file.ciphertext)?;
+
+        let plaintext = cipher(passphrase, &salt, &params)?
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map(SecretBytes::new)
+            .map_err(|_| "Wrong passphrase or corrupted key store")?;
+        let content: StoreContent = serde_json::from_slice(&plaintext)?;
+
+        let keys = content
+            .keys
+            .into_iter()
+            .map(|(alias, key)| Ok((alias, hex_string_to_bytes(&key)?)))
+            .collect::<Result<_, Box<dyn Error>>>()?;
+        Ok(Self { keys })
+    }
+
+    /// Adds or replaces the key of an alias
+    pub fn insert(&mut self, alias: &str, key: SecretBytes) -> Result<(), Box<dyn Error>> {
+        check_alias(alias)?;
+        if key.is_empty() {
+            return Err("The key must not be empty".into());
+        }
+        self.keys.insert(alias.to_string(), key);
+        Ok(())
+    }
+
+    /// Removes an alias, returning whether it existed
+    pub fn remove(&mut self, alias: &str) -> bool {
+        self.keys.remove(alias).is_some()
+    }
+
+    pub fn get(&self, alias: &str) -> Option<&[u8]> {
+        self.keys.get(alias).map(SecretBytes::as_slice)
+    }
+
+    /// Returns the sorted aliases
+    pub fn aliases(&self) -> Vec<&str> {
+        self.keys.keys().map(String::as_str).collect()
+    }
+}
+
+/// Writes a new file that only its owner can read and syncs it to disk,
+/// replacing a leftover file of an earlier failed save
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let _ = fs::remove_file(path);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Derives the AES-256-GCM key from the passphrase with Argon2id
+fn cipher(passphrase: &str, salt: &[u8], params: &Params) -> Result<Aes256Gcm, Box<dyn Error>> {
+    let mut key = Zeroizing::new([0; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(Aes256Gcm::new_from_slice(key.as_slice()).expect("32 byte key"))
+}
+
+/// Checks that an alias is non-empty and only uses letters, digits and `_-./`
+fn check_alias(alias: &str) -> Result<(), Box<dyn Error>> {
+    if alias.is_empty() || !alias.chars().all(|c| c.is_ascii_alphanumeric() || "_-./".contains(c)) {
+        return Err(format!("Invalid key alias `{}`: use letters, digits and _-./", alias).into());
+    }
+    Ok(())
+}
+
+/// Returns whether the key input references the key store, e.g. `@BMS/P1/prod`
+pub fn is_reference(key: &str) -> bool {
+    key.starts_with('@')
+}
+
+/// Returns the key of an `@alias` reference from the store, or decodes a hex key
+pub fn resolve_key(key: &str, store: Option<&KeyStore>) -> Result<SecretBytes, Box<dyn Error>> {
+    let Some(alias) = key.strip_prefix('@') else {
+        return parse_hex_input(key).map_err(|e| format!("Invalid key: {}", e).into());
+    };
+
+    let store = store.ok_or_else(|| format!("Key store is locked, cannot resolve @{}", alias))?;
+    store
+        .get(alias)
+        .map(SecretBytes::from)
+        .ok_or_else(|| format!("Unknown key alias @{}", alias).into())
+}
@@ -0,0 +1,24 @@
+//! Seed to key derivation for automotive ECU security access.
+//!
+//! The GUI in `main.rs` is a thin front end over this library; test benches and
+//! flashing scripts can depend on it directly to reuse the same derivation.
+
+pub mod audit;
+pub mod batch;
+pub mod can;
+pub mod client;
+pub mod crypto;
+pub mod doip;
+pub mod isotp;
+pub mod keystore;
+pub mod mask;
+pub mod service;
+pub mod simulator;
+pub mod tcp;
+pub mod uds;
+pub mod util;
+
+mod calculator;
+
+pub use calculator::{find_matching_entries, SeedKeyCalculator};
+pub use mask::{EcuType, MaskEntry, MaskTable, SecurityLevel};
@@ -1,66 +1,386 @@
 #![windows_subsystem = "windows"]
 
-use iced::widget::{button, column, container, pick_list, row, text, text_input, horizontal_space, vertical_space, stack, opaque, mouse_area, center};
-use iced::{Color, Element, Font, Length, Settings, window, Padding, alignment};
+use std::path::PathBuf;
+
+use iced::widget::{button, checkbox, column, combo_box, container, pick_list, rich_text, row, scrollable, span, text, text_input, horizontal_space, vertical_space, stack, opaque, mouse_area, center, Space};
+use iced::widget::text::Span;
+use iced::{event, Color, Element, Event, Font, Length, Settings, Subscription, Task, window, Padding, alignment};
 use once_cell::sync::Lazy;
 use image::GenericImageView;
-use std::error::Error;
-use arboard;
 
-mod util;
-mod crypto;
+mod cli;
+mod ecu_selector;
+mod history;
+mod process;
 
-// Import necessary utilities for mask calculation
-use util::{hex_string_to_bytes, xor_bytes};
+use ecu_selector::{EcuChoice, EcuSelector};
+use history::{History, HistoryEntry};
 
-include!(concat!(env!("OUT_DIR"), "/generated_ecu_mask.rs"));
+use seed2cmac::audit::{AuditEvent, AuditLog};
+use seed2cmac::keystore::{is_reference, resolve_key, KeyStore};
+use seed2cmac::batch::{self, BatchFormat};
+use seed2cmac::uds;
+use seed2cmac::util::{bytes_to_hex_string, bytes_to_secret_hex, check_hex_input, normalize_hex_input, parse_hex_input, HexInputCheck, OutputFormat, SecretString};
+use seed2cmac::{find_matching_entries, EcuType, MaskTable, SecurityLevel, SeedKeyCalculator};
 
 static HIGHLIGHT_COLOR: Lazy<Color> = Lazy::new(|| Color::from_rgb8(0, 191, 255)); // Deep Sky Blue
-
-// EcuType and SecurityLevel enums are dynamically generated from ecu_mask.txt in build.rs
+static ERROR_COLOR: Lazy<Color> = Lazy::new(|| Color::from_rgb(1.0, 0.0, 0.0));
 
 #[derive(Debug)]
 struct Seed2Cmac {
+    mask_table: MaskTable,
+    ecu_selector: EcuSelector,
     ecu_type: Option<EcuType>,
     security_level: Option<SecurityLevel>,
-    seed_input: String,
-    key_input: String,
-    key_output: String,
+    seed_input: SecretString,
+    key_input: SecretString,
+    key_output: SecretString,
     error_message: Option<String>,
     show_error: bool,
+    /// Result of the last verification, shown until dismissed
+    verification: Option<String>,
+    /// Summary of the last batch file dropped onto the window, shown until dismissed
+    batch_summary: Option<String>,
+    /// Key store file, `None` if there is none to unlock
+    key_store_path: Option<PathBuf>,
+    /// Unlocked key store whose aliases can be picked instead of typing a key
+    key_store: Option<KeyStore>,
+    passphrase_input: SecretString,
+    /// Audit log every calculated key is recorded in, `None` if there is no
+    /// config directory, which refuses calculations
+    audit_log: Option<AuditLog>,
+    /// Format the key is copied to the clipboard and batch files are written in
+    output_format: OutputFormat,
+    /// Calculates the key as soon as the seed and key fields are valid
+    auto_calculate: bool,
+    /// Last calculation recorded in the audit log and history, so that automatic
+    /// calculation does not record the same result again on every change
+    last_recorded: Option<AuditEvent>,
+    /// Calculations of the session, kept when the inputs are cleared
+    history: History,
+    show_history: bool,
+    /// Outcome of the last history export, shown in the history panel
+    history_notice: Option<String>,
 }
 
-impl Default for Seed2Cmac {
-    fn default() -> Self {
-        // Get the first ECU type from the generated list
-        // all_ecu_types().first() returns an Option<&EcuType>, so we map it to an Option<EcuType>
-        let first_ecu = all_ecu_types().first().copied();
-        
-        // Get the first security level from the generated list
-        let first_security_level = all_security_levels().first().copied();
-        
+impl Seed2Cmac {
+    /// Creates the application state, showing `load_error` if loading the mask files failed
+    fn new(mask_table: MaskTable, load_error: Option<String>, key_store_path: Option<PathBuf>, audit_log: Option<AuditLog>) -> Self {
+        // Select the first ECU type of the loaded table and its first security level
+        let first_ecu = mask_table
+            .ecu_types()
+            .first()
+            .cloned()
+            .unwrap_or_else(|| panic!("No ECU types available"));
+        let first_security_level = mask_table.security_levels_for(&first_ecu).first().copied();
+        let ecu_selector = EcuSelector::load(&mask_table);
+
         Self {
-            ecu_type: Some(first_ecu.unwrap_or_else(|| panic!("No ECU types available"))),
+            ecu_type: Some(first_ecu),
             security_level: Some(first_security_level.unwrap_or_else(|| panic!("No security levels available"))),
-            seed_input: String::new(),
-            key_input: String::new(),
-            key_output: String::new(),
-            error_message: None,
-            show_error: false,
+            mask_table,
+            ecu_selector,
+            seed_input: SecretString::default(),
+            key_input: SecretString::default(),
+            key_output: SecretString::default(),
+            show_error: load_error.is_some(),
+            error_message: load_error,
+            verification: None,
+            batch_summary: None,
+            key_store_path: key_store_path.filter(|path| path.exists()),
+            key_store: None,
+            passphrase_input: SecretString::default(),
+            audit_log,
+            output_format: OutputFormat::default(),
+            auto_calculate: false,
+            last_recorded: None,
+            history: History::load(),
+            show_history: false,
+            history_notice: None,
+        }
+    }
+
+    /// Selects an ECU, keeping the security level if the ECU supports it and
+    /// otherwise selecting its first level
+    fn select_ecu(&mut self, ecu_type: EcuType) {
+        let security_levels = self.mask_table.security_levels_for(&ecu_type);
+        if !self.security_level.is_some_and(|level| security_levels.contains(&level)) {
+            self.security_level = security_levels.first().copied();
+        }
+        self.ecu_type = Some(ecu_type);
+    }
+
+    /// Replaces a requestSeed response frame pasted into the seed field, e.g.
+    /// `67 01 A1 B2 …`, by its seed and selects the security level of the frame
+    fn apply_seed_frame(&mut self) {
+        let Some(ecu) = &self.ecu_type else {
+            return;
+        };
+        let Ok(response) = uds::parse_hex_frame(&self.seed_input).and_then(|frame| uds::parse_seed_response(&frame)) else {
+            return;
+        };
+
+        // A bare seed is never mistaken for a frame, the frame is two bytes longer
+        if self
+            .mask_table
+            .get(ecu, response.level)
+            .is_some_and(|entry| entry.seed_len() == response.seed.len())
+        {
+            self.security_level = Some(response.level);
+            self.seed_input = bytes_to_secret_hex(&response.seed);
+        }
+    }
+
+    /// Returns the calculator of the selected ECU and security level
+    fn calculator(&self) -> Option<SeedKeyCalculator> {
+        let (ecu, level) = self.ecu_type.as_ref().zip(self.security_level)?;
+        SeedKeyCalculator::from_table(&self.mask_table, ecu, level).ok()
+    }
+
+    /// Returns whether the seed and key fields hold a seed and a key of lengths
+    /// the selected algorithm accepts
+    fn inputs_valid(&self) -> bool {
+        let Some(calculator) = self.calculator() else {
+            return false;
+        };
+        check_hex_input(&self.seed_input).is_complete(calculator.seed_len())
+            && self
+                .key_hex()
+                .ok()
+                .and_then(|key| parse_hex_input(&key).ok())
+                .is_some_and(|key| calculator.check_key(&key).is_ok())
+    }
+
+    /// Calculates the key if automatic calculation is on and the inputs are valid
+    fn auto_calculate(&mut self) {
+        if self.auto_calculate && self.inputs_valid() {
+            self.calculate(true);
+        }
+    }
+
+    /// Calculates the key and records it in the audit log and history. An
+    /// automatic calculation of the last recorded result is not recorded again.
+    fn calculate(&mut self, automatic: bool) {
+        self.error_message = None;
+        self.show_error = false;
+
+        let (Some(ecu), Some(level)) = (self.ecu_type.clone(), self.security_level) else {
+            return;
+        };
+        let result = SeedKeyCalculator::from_table(&self.mask_table, &ecu, level)
+            .and_then(|calculator| calculator.calculate_hex(&self.seed_input, &self.key_hex()?))
+            .and_then(|cmac_hex| {
+                let event = self.audit_event("gui", &ecu, level, &cmac_hex)?;
+                // The key is only shown once its calculation is on record
+                let recorded = automatic && self.last_recorded.as_ref() == Some(&event);
+                if !recorded {
+                    self.audit(&event)?;
+                }
+                Ok((cmac_hex, event, recorded))
+            });
+        match result {
+            Ok((cmac_hex, event, recorded)) => {
+                if !recorded {
+                    let key = self.key_input.clone();
+                    self.history.record(HistoryEntry::new(ecu.as_str(), &level.to_string(), &self.seed_input, &key, cmac_hex.clone()));
+                    self.ecu_selector.record_use(&ecu, &self.mask_table);
+                    self.last_recorded = Some(event);
+                }
+                self.key_output = cmac_hex;
+            }
+            Err(err) => {
+                self.error_message = Some(err.to_string());
+                self.show_error = true;
+            }
+        }
+    }
+
+    /// Puts an earlier calculation back into the inputs, along with its key if
+    /// that was an `@alias`
+    fn restore(&mut self, entry: HistoryEntry) {
+        if let Some(ecu) = self.mask_table.ecu(&entry.ecu) {
+            self.select_ecu(ecu);
+        }
+        if let Ok(level) = entry.level.parse::<SecurityLevel>() {
+            self.security_level = Some(level);
+        }
+        if is_reference(&entry.key) {
+            self.key_input = entry.key.into();
+        }
+        self.seed_input = entry.seed.into();
+        self.key_output = entry.result;
+        self.error_message = None;
+        self.show_error = false;
+    }
+
+    fn copy_to_clipboard(&mut self, text: &str) {
+        match arboard::Clipboard::new() {
+            Ok(mut clipboard) => {
+                if let Err(err) = clipboard.set_text(text.to_string()) {
+                    self.error_message = Some(format!("复制到剪贴板失败: {}", err));
+                    self.show_error = true;
+                }
+            }
+            Err(err) => {
+                self.error_message = Some(format!("无法访问剪贴板: {}", err));
+                self.show_error = true;
+            }
+        }
+    }
+
+    /// Returns the key of the key field as hex, looking up an `@alias` in the
+    /// unlocked key store
+    fn key_hex(&self) -> Result<SecretString, Box<dyn std::error::Error>> {
+        if !is_reference(&self.key_input) {
+            return Ok(self.key_input.clone());
+        }
+        let key = resolve_key(&self.key_input, self.key_store.as_ref()).map_err(|_| "密钥库未解锁或找不到该密钥别名")?;
+        Ok(bytes_to_secret_hex(&key))
+    }
+
+    /// Describes a response calculated or checked for the seed field, `gui` or
+    /// `gui-verify` as `source`
+    fn audit_event(&self, source: &str, ecu: &EcuType, level: SecurityLevel, response_hex: &str) -> Result<AuditEvent, Box<dyn std::error::Error>> {
+        let seed = parse_hex_input(&self.seed_input)?;
+        let response = parse_hex_input(response_hex)?;
+        Ok(AuditEvent::new(source, &ecu.to_string(), level, &seed, &response))
+    }
+
+    /// Records the event in the audit log
+    fn audit(&self, event: &AuditEvent) -> Result<(), Box<dyn std::error::Error>> {
+        let audit_log = self.audit_log.as_ref().ok_or("没有可用的审计日志，无法记录计算")?;
+        audit_log.record(event).map_err(|e| format!("写入审计日志失败: {}", e).into())
+    }
+
+    /// Checks the response in the output field against the seed and key and
+    /// describes the result, listing the table entries that produce the
+    /// response on a mismatch
+    fn verify(&self, ecu: &EcuType, level: SecurityLevel) -> Result<String, Box<dyn std::error::Error>> {
+        let calculator = SeedKeyCalculator::from_table(&self.mask_table, ecu, level)?;
+        let key_hex = self.key_hex()?;
+        let matches = calculator.verify_hex(&self.seed_input, &key_hex, &self.key_output)?;
+        // Like a calculation, the outcome is only shown once it is on record
+        self.audit(&self.audit_event("gui-verify", ecu, level, &self.key_output)?)?;
+        if matches {
+            return Ok(format!("Key匹配：与{} {}的预期响应一致", ecu, level));
+        }
+
+        let seed = parse_hex_input(&self.seed_input)?;
+        let key = parse_hex_input(&key_hex)?;
+        let response = parse_hex_input(&self.key_output)?;
+        let candidates: Vec<String> = find_matching_entries(&self.mask_table, &seed, &key, &response)
+            .into_iter()
+            .map(|entry| format!("{} {} = {}", entry.ecu, entry.level, bytes_to_hex_string(&entry.mask)))
+            .collect();
+
+        if candidates.is_empty() {
+            Ok(format!("Key不匹配：与{} {}的预期响应不一致，掩码表中也没有能产生该Key的条目", ecu, level))
+        } else {
+            Ok(format!("Key不匹配：与{} {}的预期响应不一致，该Key可能来自:\n{}", ecu, level, candidates.join("\n")))
         }
     }
 }
 
+/// Shows the number of hex digits of a field against the nearest accepted
+/// length, `accepted` being the accepted lengths in bytes or empty for any
+fn hex_counter<'a>(check: &HexInputCheck, accepted: &[usize]) -> iced::widget::Text<'a> {
+    let target = accepted.iter().map(|len| len * 2).filter(|digits| *digits >= check.digits).min().or(accepted.iter().map(|len| len * 2).max());
+    let complete = check.invalid.is_empty() && check.digits > 0 && check.digits.is_multiple_of(2) && target.is_none_or(|digits| digits == check.digits);
+    let counter = match target {
+        Some(digits) => text(format!("{}/{}", check.digits, digits)),
+        None => text(check.digits.to_string()),
+    }
+    .size(12)
+    .width(Length::Fixed(50.0));
+
+    if complete {
+        counter.color(*HIGHLIGHT_COLOR)
+    } else if !check.invalid.is_empty() || target.is_some_and(|digits| check.digits > digits) {
+        counter.color(*ERROR_COLOR)
+    } else {
+        counter
+    }
+}
+
+/// Repeats the input under its field with the characters that are not hex in
+/// red, the others as dots if the field is `masked`, nothing if there are none
+fn invalid_chars<'a>(input: &str, check: &HexInputCheck, masked: bool) -> Option<Element<'a, Message>> {
+    if check.invalid.is_empty() {
+        return None;
+    }
+    let styled = |part: &str, invalid: bool| -> Span<'a, Message> {
+        if invalid {
+            span(part.to_string()).size(12).color(*ERROR_COLOR).underline(true)
+        } else if masked {
+            span("•".repeat(part.chars().count())).size(12)
+        } else {
+            span(part.to_string()).size(12)
+        }
+    };
+
+    // Runs of valid and invalid characters, positions counting from 1
+    let mut spans = Vec::new();
+    let (mut start, mut run_invalid) = (0, false);
+    for (index, (offset, _)) in input.char_indices().enumerate() {
+        let invalid = check.invalid.iter().any(|(position, _)| *position == index + 1);
+        if invalid != run_invalid {
+            if offset > start {
+                spans.push(styled(&input[start..offset], run_invalid));
+            }
+            (start, run_invalid) = (offset, invalid);
+        }
+    }
+    spans.push(styled(&input[start..], run_invalid));
+
+    let (position, character) = check.invalid[0];
+    Some(
+        row![
+            Space::with_width(Length::Fixed(80.0)),
+            column![
+                rich_text(spans),
+                text(format!("第{}个字符“{}”不是十六进制数字", position, character)).size(12).color(*ERROR_COLOR),
+            ],
+        ]
+        .spacing(10)
+        .into(),
+    )
+}
+
+/// Normalizes text pasted into a hex field, e.g. `0xA1, 0xB2, …` or base64, to
+/// contiguous hex digits, and keeps typed text as it is
+fn reformat_pasted(previous: &str, input: SecretString) -> SecretString {
+    let pasted = input.chars().count() > previous.chars().count() + 1;
+    match normalize_hex_input(&input) {
+        Ok(hex) if pasted => hex,
+        _ => input,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Message {
-    EcuTypeSelected(EcuType),
+    EcuTypeSelected(EcuChoice),
     SecurityLevelSelected(SecurityLevel),
-    SeedInputChanged(String),
-    KeyInputChanged(String),
-    KeyOutputChanged(String),
+    SeedInputChanged(SecretString),
+    KeyInputChanged(SecretString),
+    KeyOutputChanged(SecretString),
     Calculate,
+    Verify,
     Clear,
     DismissError,
+    DismissVerification,
+    FileDropped(PathBuf),
+    PassphraseChanged(SecretString),
+    UnlockKeyStore,
+    KeyAliasSelected(String),
+    DismissBatchSummary,
+    AutoCalculateToggled(bool),
+    ShowHistory,
+    DismissHistory,
+    RestoreHistory(usize),
+    CopyHistory(usize),
+    ExportHistory,
+    PersistHistoryToggled(bool),
+    OutputFormatSelected(OutputFormat),
     CopyToClipboard,
     ClipboardError(String),
 }
@@ -72,36 +392,45 @@ impl Seed2Cmac {
 
     fn update(&mut self, message: Message) {
         match message {
-            Message::EcuTypeSelected(ecu_type) => {
-                self.ecu_type = Some(ecu_type);
+            Message::EcuTypeSelected(choice) => {
+                self.select_ecu(choice.ecu);
                 self.error_message = None;
                 self.show_error = false;
+                self.auto_calculate();
             }
             Message::SecurityLevelSelected(security_level) => {
                 self.security_level = Some(security_level);
                 self.error_message = None;
                 self.show_error = false;
+                self.auto_calculate();
             }
             Message::SeedInputChanged(input) => {
-                self.seed_input = input;
+                self.seed_input = reformat_pasted(&self.seed_input, input);
+                self.apply_seed_frame();
                 self.error_message = None;
                 self.show_error = false;
+                self.auto_calculate();
             }
             Message::KeyInputChanged(input) => {
-                self.key_input = input;
+                self.key_input = if is_reference(&input) { input } else { reformat_pasted(&self.key_input, input) };
                 self.error_message = None;
                 self.show_error = false;
+                self.auto_calculate();
+            }
+            Message::KeyOutputChanged(output) => {
+                // The output field also takes the recorded response to verify
+                self.key_output = output;
             }
-            Message::KeyOutputChanged(_) => {}
             Message::Calculate => {
+                self.calculate(false);
+            }
+            Message::Verify => {
                 self.error_message = None;
                 self.show_error = false;
-                
-                if let (Some(ecu), Some(level)) = (self.ecu_type, self.security_level) {
-                    match self.calculate_cmac_key(ecu, level) {
-                        Ok(cmac_hex) => {
-                            self.key_output = cmac_hex;
-                        },
+
+                if let (Some(ecu), Some(level)) = (&self.ecu_type, self.security_level) {
+                    match self.verify(ecu, level) {
+                        Ok(verification) => self.verification = Some(verification),
                         Err(err) => {
                             self.error_message = Some(err.to_string());
                             self.show_error = true;
@@ -110,33 +439,134 @@ impl Seed2Cmac {
                 }
             }
             Message::Clear => {
-                self.seed_input = String::new();
-                self.key_input = String::new();
-                self.key_output = String::new();
+                self.seed_input = SecretString::default();
+                self.key_input = SecretString::default();
+                self.key_output = SecretString::default();
                 self.error_message = None;
                 self.show_error = false;
             }
             Message::DismissError => {
                 self.show_error = false;
             }
-            Message::CopyToClipboard => {
-                if !self.key_output.is_empty() {
-                    match arboard::Clipboard::new() {
-                        Ok(mut clipboard) => {
-                            match clipboard.set_text(self.key_output.clone()) {
-                                Ok(_) => {},
-                                Err(err) => {
-                                    self.error_message = Some(format!("复制到剪贴板失败: {}", err));
-                                    self.show_error = true;
-                                }
-                            }
-                        },
+            Message::DismissVerification => {
+                self.verification = None;
+            }
+            Message::FileDropped(path) if BatchFormat::from_path(&path).is_none() => {
+                self.error_message = Some(format!("不支持的文件类型: {}，请拖入.csv、.jsonl或.ndjson批量文件", path.display()));
+                self.show_error = true;
+            }
+            Message::FileDropped(path) => {
+                // The key field is the default key of rows without one
+                let default_key = Some(self.key_input.as_str()).filter(|key| !key.is_empty());
+                let output = batch::output_path(&path);
+                let result = self
+                    .audit_log
+                    .as_ref()
+                    .ok_or_else(|| "没有可用的审计日志，无法记录计算".into())
+                    .and_then(|audit_log| {
+                        batch::run(&self.mask_table, &path, &output, default_key, self.key_store.as_ref(), Some(audit_log), self.output_format)
+                    });
+                match result {
+                    Ok(summary) => {
+                        self.batch_summary = Some(format!(
+                            "共{}行，失败{}行，结果已写入:\n{}",
+                            summary.rows,
+                            summary.failed,
+                            output.display()
+                        ));
+                    }
+                    Err(err) => {
+                        self.error_message = Some(format!("批量计算失败: {}", err));
+                        self.show_error = true;
+                    }
+                }
+            }
+            Message::PassphraseChanged(passphrase) => {
+                self.passphrase_input = passphrase;
+            }
+            Message::UnlockKeyStore => {
+                if let Some(path) = &self.key_store_path {
+                    match KeyStore::open(path, &self.passphrase_input) {
+                        Ok(key_store) => self.key_store = Some(key_store),
                         Err(err) => {
-                            self.error_message = Some(format!("无法访问剪贴板: {}", err));
+                            self.error_message = Some(format!("解锁密钥库失败: {}", err));
                             self.show_error = true;
                         }
                     }
                 }
+                self.passphrase_input = SecretString::default();
+            }
+            Message::KeyAliasSelected(alias) => {
+                // The key field only ever shows the alias, never the key
+                self.key_input = format!("@{}", alias).into();
+                self.error_message = None;
+                self.show_error = false;
+                self.auto_calculate();
+            }
+            Message::DismissBatchSummary => {
+                self.batch_summary = None;
+            }
+            Message::AutoCalculateToggled(enabled) => {
+                self.auto_calculate = enabled;
+                self.auto_calculate();
+            }
+            Message::OutputFormatSelected(format) => {
+                self.output_format = format;
+            }
+            Message::CopyToClipboard => {
+                if !self.key_output.is_empty() {
+                    let formatted = match (parse_hex_input(&self.key_output), self.security_level) {
+                        (Ok(key), Some(level)) => self.output_format.format(&key, level),
+                        (Err(err), _) => {
+                            self.error_message = Some(format!("无法按所选格式复制Key: {}", err));
+                            self.show_error = true;
+                            return;
+                        }
+                        (Ok(_), None) => {
+                            self.error_message = Some("请选择安全等级".to_string());
+                            self.show_error = true;
+                            return;
+                        }
+                    };
+                    self.copy_to_clipboard(&formatted);
+                }
+            }
+            Message::ShowHistory => {
+                self.show_history = true;
+            }
+            Message::DismissHistory => {
+                self.show_history = false;
+                self.history_notice = None;
+            }
+            Message::RestoreHistory(index) => {
+                if let Some(entry) = self.history.entries().get(index).cloned() {
+                    self.restore(entry);
+                    self.show_history = false;
+                    self.history_notice = None;
+                }
+            }
+            Message::CopyHistory(index) => {
+                let formatted = self.history.entries().get(index).and_then(|entry| {
+                    let level = entry.level.parse::<SecurityLevel>().ok()?;
+                    Some(self.output_format.format(&parse_hex_input(&entry.result).ok()?, level))
+                });
+                if let Some(formatted) = formatted {
+                    self.copy_to_clipboard(&formatted);
+                }
+            }
+            Message::ExportHistory => {
+                let result = history::export_path()
+                    .ok_or_else(|| "找不到下载目录".into())
+                    .and_then(|path| self.history.export_csv(&path).map(|_| path));
+                self.history_notice = Some(match result {
+                    Ok(path) => format!("已导出到: {}", path.display()),
+                    Err(err) => format!("导出失败: {}", err),
+                });
+            }
+            Message::PersistHistoryToggled(persistent) => {
+                if let Err(err) = self.history.set_persistent(persistent) {
+                    self.history_notice = Some(format!("保存历史记录失败: {}", err));
+                }
             }
             Message::ClipboardError(error) => {
                 self.error_message = Some(error);
@@ -145,49 +575,21 @@ impl Seed2Cmac {
         }
     }
     
-    /// Calculate CMAC key using the seed, key, ECU type, and security level
-    fn calculate_cmac_key(&self, ecu: EcuType, level: SecurityLevel) -> Result<String, Box<dyn Error>> {
-        // Validate and convert seed to bytes
-        if self.seed_input.is_empty() {
-            return Err("输入的Seed不能为空".into());
-        }
-        
-        let seed = match hex_string_to_bytes(&self.seed_input) {
-            Ok(bytes) => bytes,
-            Err(_) => return Err("无效的Seed输入：必须是32个字符的十六进制字符串".into()),
-        };
-        
-        // Get the security level as a u8
-        let level_str = level.to_string();
-        let level_num = level_str.parse::<u8>().map_err(|_| "Invalid security level")?;
-        
-        // Get the mask for the selected ECU and security level
-        if let Some(mask_str) = get_matched_mask(&ecu.to_string(), level_num) {
-            let mask = match hex_string_to_bytes(mask_str) {
-                Ok(bytes) => bytes,
-                Err(_) => return Err("配置中的掩码无效".into()),
-            };
-            
-            // XOR the seed with the mask
-            let mask_value = match xor_bytes(&seed, &mask) {
-                Ok(bytes) => bytes,
-                Err(e) => return Err(format!("异或操作失败: {}", e).into()),
-            };
-            
-            crypto::calculate_cmac_key(&self.key_input, &mask_value)
-        } else {
-            Err(format!("找不到ECU: {} 与安全等级: {} 对应的掩码", ecu, level_num).into())
-        }
+    /// Turns CSV or JSON Lines files dropped onto the window into batch runs
+    fn subscription(&self) -> Subscription<Message> {
+        event::listen_with(|event, _status, _window| match event {
+            Event::Window(window::Event::FileDropped(path)) => Some(Message::FileDropped(path)),
+            _ => None,
+        })
     }
 
-    fn view(&self) -> Element<Message> {
-        // ECU Type picker
+    fn view(&self) -> Element<'_, Message> {
+        // ECU Type picker, recently used and grouped ECU types filtered while typing
         let ecu_type_text = text("ECU选型:").size(16).width(Length::Fixed(80.0));
-        // Use the dynamically generated list of ECU types
-        let ecu_types = all_ecu_types();
-        let ecu_picker = pick_list(
-            ecu_types,
-            self.ecu_type,
+        let ecu_picker = combo_box(
+            self.ecu_selector.state(),
+            "输入名称筛选",
+            self.ecu_type.as_ref().and_then(|ecu| self.ecu_selector.selection(ecu)),
             Message::EcuTypeSelected,
         )
         .padding(8)
@@ -195,8 +597,12 @@ impl Seed2Cmac {
 
         // Security Level picker
         let security_level_text = text("安全等级:").size(16).width(Length::Fixed(80.0));
-        // Use the dynamically generated list of security levels
-        let security_levels = all_security_levels();
+        // Only offer the security levels the selected ECU supports
+        let security_levels = self
+            .ecu_type
+            .as_ref()
+            .map(|ecu| self.mask_table.security_levels_for(ecu))
+            .unwrap_or_default();
         let security_picker = pick_list(
             security_levels,
             self.security_level,
@@ -206,31 +612,87 @@ impl Seed2Cmac {
         .width(Length::Fixed(150.0));
 
         // Note text
+        // Seed, key and response lengths of the algorithm of the selected mask table entry
+        let calculator = self.calculator();
+        let seed_len = calculator.as_ref().map_or(16, |calculator| calculator.seed_len());
+        let response_len = calculator.as_ref().map_or(16, |calculator| calculator.response_len());
+        let algorithm = calculator.as_ref().map_or("cmac", |calculator| calculator.algorithm().name()).to_ascii_uppercase();
+        let key_lengths: Vec<String> = calculator
+            .as_ref()
+            .map_or(vec![16, 24, 32], |calculator| calculator.key_lengths())
+            .iter()
+            .map(|len| len.to_string())
+            .collect();
+        let key_len = match key_lengths.split_last() {
+            None => String::from("任意"),
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{}或{}", rest.join("、"), last),
+        };
+
         let note_text = column![
             text("注意事项:").size(14).color(*HIGHLIGHT_COLOR),
-            text("1.Seed和Key数据长度为16个字节，格式为十六进制，数据前面不需要加0X'或'0x';").size(14).color(*HIGHLIGHT_COLOR),
-            text("2.计算出的CMAC Key长度为16个字节，显示格式为十六进制。").size(14).color(*HIGHLIGHT_COLOR),
+            text(format!("1.Seed长度为{}个字节，Key长度为{}个字节，格式为十六进制，数据前面不需要加0X'或'0x';", seed_len, key_len)).size(14).color(*HIGHLIGHT_COLOR),
+            text(format!("2.使用{}算法，计算出的Key长度为{}个字节，显示格式为十六进制。", algorithm, response_len)).size(14).color(*HIGHLIGHT_COLOR),
         ].spacing(5);
 
         // Seed input
         let seed_label = text("输入Seed:").size(16).width(Length::Fixed(80.0));
+        let seed_placeholder = format!("{}个十六进制字符，或完整的67响应", seed_len * 2);
         let seed_input = text_input(
-            "Ox",
+            &seed_placeholder,
             &self.seed_input
         )
-        .on_input(Message::SeedInputChanged)
+        .on_input(|input| Message::SeedInputChanged(input.into()))
         .padding(8)
         .width(Length::Fixed(400.0));
+        let seed_check = check_hex_input(&self.seed_input);
+        let seed_counter = hex_counter(&seed_check, &[seed_len]);
 
-        // Key input
+        // Key input, masked unless it holds an `@alias`
         let key_label = text("输入Key:").size(16).width(Length::Fixed(80.0));
         let key_input = text_input(
             "Ox",
             &self.key_input
         )
-        .on_input(Message::KeyInputChanged)
-        .padding(8)
-        .width(Length::Fixed(400.0));
+        .secure(!is_reference(&self.key_input))
+        .on_input(|input| Message::KeyInputChanged(input.into()))
+        .padding(8);
+
+        // Aliases are resolved on calculation and not counted
+        let key_check = Some(&self.key_input).filter(|key| !is_reference(key)).map(|key| check_hex_input(key));
+        let key_counter = match &key_check {
+            Some(check) => hex_counter(check, &calculator.as_ref().map_or(vec![16, 24, 32], |calculator| calculator.key_lengths())),
+            None => text(""),
+        };
+
+        // Alias picker of the unlocked key store, or the passphrase to unlock it
+        let key_row: Element<'_, Message> = match (&self.key_store_path, &self.key_store) {
+            (_, Some(key_store)) => {
+                let aliases: Vec<String> = key_store.aliases().into_iter().map(String::from).collect();
+                let selected = self.key_input.strip_prefix('@').map(String::from).filter(|alias| aliases.contains(alias));
+                row![
+                    key_input.width(Length::Fixed(240.0)),
+                    pick_list(aliases, selected, Message::KeyAliasSelected)
+                        .placeholder("选择密钥")
+                        .padding(8)
+                        .width(Length::Fixed(150.0)),
+                ].spacing(10).into()
+            }
+            (Some(_), None) => row![
+                key_input.width(Length::Fixed(240.0)),
+                text_input("密钥库口令", &self.passphrase_input)
+                    .secure(true)
+                    .on_input(|passphrase| Message::PassphraseChanged(passphrase.into()))
+                    .on_submit(Message::UnlockKeyStore)
+                    .padding(8)
+                    .width(Length::Fixed(95.0)),
+                button(text("解锁").center())
+                    .style(button::secondary)
+                    .on_press(Message::UnlockKeyStore)
+                    .width(Length::Fixed(55.0)),
+            ].spacing(5).into(),
+            (None, None) => key_input.width(Length::Fixed(400.0)).into(),
+        };
 
         // CMAC Key output
         let cmac_key_label = text("CMAC Key:").size(16).width(Length::Fixed(80.0));
@@ -238,9 +700,9 @@ impl Seed2Cmac {
             "Ox",
             &self.key_output
         )
-        .on_input(Message::KeyOutputChanged)
+        .on_input(|output| Message::KeyOutputChanged(output.into()))
         .padding(8)
-        .width(Length::Fixed(340.0));
+        .width(Length::Fixed(250.0));
         
         // Copy button for CMAC key
         let copy_button = button(text("copy"))
@@ -249,23 +711,46 @@ impl Seed2Cmac {
             .height(iced::Fill)
             .width(Length::Fixed(60.0));
 
+        // Format the key is copied in
+        let format_picker = pick_list(&OutputFormat::ALL[..], Some(self.output_format), Message::OutputFormatSelected)
+            .padding(8)
+            .width(Length::Fixed(90.0));
+
         // Buttons
+        // Only enabled once the seed and key are valid
         let calculate_button = button(text("计算").center())
             .style(button::primary)
-            .on_press(Message::Calculate)
-            .width(Length::Fixed(180.0))
+            .on_press_maybe(self.inputs_valid().then_some(Message::Calculate))
+            .width(Length::Fixed(150.0))
+            .height(Length::Fixed(50.0))
+            .padding(10);
+
+        // Checks the key response in the output field, e.g. one rejected by the ECU
+        let verify_button = button(text("校验").center())
+            .style(button::primary)
+            .on_press(Message::Verify)
+            .width(Length::Fixed(150.0))
             .height(Length::Fixed(50.0))
             .padding(10);
 
         let clear_button = button(text("清空").center())
             .style(button::secondary)
             .on_press(Message::Clear)
-            .width(Length::Fixed(180.0))
+            .width(Length::Fixed(150.0))
             .height(Length::Fixed(50.0))
             .padding(10);
 
+        let auto_checkbox = checkbox("自动计算", self.auto_calculate)
+            .on_toggle(Message::AutoCalculateToggled)
+            .size(16)
+            .text_size(14);
+
         // Footer
         let footer_text = text("Any feedback or issues, please contact us.").size(12);
+        let history_button = button(text(format!("历史记录({})", self.history.entries().len())).size(12))
+            .style(button::text)
+            .on_press(Message::ShowHistory)
+            .padding(0);
 
         // Main layout
         let content = column![
@@ -288,32 +773,44 @@ impl Seed2Cmac {
                 row![   
                     seed_label,
                     seed_input,
+                    seed_counter,
                 ].spacing(10).align_y(alignment::Vertical::Center),
-                
+            ]
+            .push_maybe(invalid_chars(&self.seed_input, &seed_check, false))
+            .push(
                 row![
                     key_label,
-                    key_input,
+                    key_row,
+                    key_counter,
                 ].spacing(10).align_y(alignment::Vertical::Center),
-
+            )
+            .push_maybe(key_check.as_ref().and_then(|check| invalid_chars(&self.key_input, check, true)))
+            .push(
                 row![
                     cmac_key_label,
                     row![
                         cmac_key_output,
-                        copy_button
+                        copy_button,
+                        format_picker,
                     ].spacing(0).height(iced::Shrink),
                 ].spacing(10).align_y(alignment::Vertical::Center),
-            ].spacing(10),
+            )
+            .spacing(10),
             
             row![
                 calculate_button,
+                verify_button,
                 clear_button,
+                auto_checkbox,
             ]
             .spacing(20)
+            .align_y(alignment::Vertical::Center)
             .padding(Padding::new(0.0).top(10.0)),
 
             vertical_space(),
             
             row![
+                history_button,
                 horizontal_space(),
                 footer_text,
             ].padding(0),
@@ -346,6 +843,89 @@ impl Seed2Cmac {
         .style(container::rounded_box);
         
         modal(main_content, error_modal, Message::DismissError)
+    } else if let Some(verification) = &self.verification {
+        let verification_modal = container(
+            column![
+                text("校验结果").size(24),
+                text(verification).size(16),
+                button(text("关闭").center())
+                    .on_press(Message::DismissVerification)
+                    .padding(10)
+            ]
+            .spacing(20)
+            .padding(20)
+        )
+        .width(Length::Fixed(400.0))
+        .padding(10)
+        .style(container::rounded_box);
+
+        modal(main_content, verification_modal, Message::DismissVerification)
+    } else if let Some(batch_summary) = &self.batch_summary {
+        let batch_modal = container(
+            column![
+                text("批量计算结果").size(24),
+                text(batch_summary).size(16),
+                button(text("关闭").center())
+                    .on_press(Message::DismissBatchSummary)
+                    .padding(10)
+            ]
+            .spacing(20)
+            .padding(20)
+        )
+        .width(Length::Fixed(400.0))
+        .padding(10)
+        .style(container::rounded_box);
+
+        modal(main_content, batch_modal, Message::DismissBatchSummary)
+    } else if self.show_history {
+        // Newest first, each with its index in the history
+        let entries = self.history.entries().iter().enumerate().rev().map(|(index, entry)| {
+            row![
+                column![
+                    text(format!("{}  {} {}  Key {}", entry.time(), entry.ecu, entry.level, entry.key)).size(12),
+                    text(format!("Seed: {}", entry.seed)).size(12),
+                    text(format!("结果: {}", entry.result.as_str())).size(12).color(*HIGHLIGHT_COLOR),
+                ].width(Length::Fill),
+                button(text("恢复").size(12))
+                    .style(button::secondary)
+                    .on_press(Message::RestoreHistory(index)),
+                button(text("复制").size(12))
+                    .style(button::secondary)
+                    .on_press(Message::CopyHistory(index)),
+            ]
+            .spacing(5)
+            .align_y(alignment::Vertical::Center)
+            .into()
+        });
+
+        let history_modal = container(
+            column![
+                text("历史记录").size(24),
+                scrollable(column(entries).spacing(10).padding(Padding::new(0.0).right(15.0))).height(Length::Fixed(240.0)),
+                row![
+                    checkbox("重启后保留", self.history.is_persistent())
+                        .on_toggle(Message::PersistHistoryToggled)
+                        .size(16)
+                        .text_size(14),
+                    horizontal_space(),
+                    button(text("导出CSV").center())
+                        .style(button::secondary)
+                        .on_press(Message::ExportHistory)
+                        .padding(10),
+                    button(text("关闭").center())
+                        .on_press(Message::DismissHistory)
+                        .padding(10),
+                ].spacing(10).align_y(alignment::Vertical::Center),
+            ]
+            .push_maybe(self.history_notice.as_deref().map(|notice| text(notice).size(12)))
+            .spacing(15)
+            .padding(20)
+        )
+        .width(Length::Fixed(540.0))
+        .padding(10)
+        .style(container::rounded_box);
+
+        modal(main_content, history_modal, Message::DismissHistory)
     } else {
         main_content.into()
     }
@@ -387,7 +967,65 @@ where
 }
 
 
+/// Attaches to the console of the parent process on Windows, where the GUI
+/// subsystem starts the program without one, so that the command line modes,
+/// `--help` and argument errors can print to the terminal they are run from.
+/// Without a parent console, e.g. when started from Explorer, nothing changes.
+#[cfg(windows)]
+fn attach_parent_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // Fails if the parent has no console or the handles were redirected, both fine
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_parent_console() {}
+
 fn main() -> iced::Result {
+    // Any argument may select a command line mode or print help or an error
+    if std::env::args_os().len() > 1 {
+        attach_parent_console();
+    }
+    let arg = cli::parse_cli();
+    let key_store = arg.key_store.as_deref();
+    let audit_log = arg.audit_log.as_deref();
+    if let Some(action) = &arg.key_store_action {
+        std::process::exit(process::run_key_store(key_store, action));
+    }
+    if arg.verify_audit_log {
+        std::process::exit(process::run_verify_audit_log(audit_log));
+    }
+    if let Some(serve) = &arg.serve {
+        std::process::exit(process::run_serve(&arg.mask_files, key_store, audit_log, serve));
+    }
+    if let Some(batch) = &arg.batch {
+        std::process::exit(process::run_batch(&arg.mask_files, key_store, audit_log, batch));
+    }
+    if let Some(simulate) = &arg.simulate {
+        std::process::exit(process::run_simulator(&arg.mask_files, key_store, simulate));
+    }
+    if let Some(unlock) = &arg.unlock {
+        std::process::exit(process::run_unlock(&arg.mask_files, key_store, audit_log, unlock));
+    }
+    if let Some(headless) = &arg.headless {
+        std::process::exit(process::run(&arg.mask_files, key_store, audit_log, headless));
+    }
+
+    // Fall back to the built-in table if any mask file fails to load
+    let (mask_table, load_error) = match MaskTable::load(&arg.mask_files) {
+        Ok(table) => (table, None),
+        Err(err) => (MaskTable::builtin(), Some(format!("加载掩码文件失败: {}", err))),
+    };
+
+    let key_store_path = arg.key_store.clone().or_else(KeyStore::default_path);
+    let audit_log = arg.audit_log.clone().or_else(AuditLog::default_path).map(AuditLog::new);
+
     let settings = Settings {
         fonts: vec![include_bytes!("../assets/fonts/NotoSansCJKtc-Regular.otf").into()],
         default_font: Font::with_name("Noto Sans CJK TC"),
@@ -396,17 +1034,18 @@ fn main() -> iced::Result {
     };
 
     let window = window::Settings {
-        min_size: Some((600.0, 370.0).into()),
-        size: (600.0, 370.0).into(),
+        min_size: Some((600.0, 475.0).into()),
+        size: (600.0, 475.0).into(),
         icon: icon(),
         ..window::Settings::default()
     };
 
     iced::application(Seed2Cmac::title, Seed2Cmac::update, Seed2Cmac::view)
+    .subscription(Seed2Cmac::subscription)
     .settings(settings)
     .window(window)
     .centered()
-    .run()
+    .run_with(move || (Seed2Cmac::new(mask_table, load_error, key_store_path, audit_log), Task::none()))
 }
 
 fn icon() -> Option<window::Icon> {
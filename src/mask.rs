@@ -0,0 +1,368 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+
+use crate::crypto::{self, CmacCipher};
+
+mod parser;
+
+pub use parser::{
+    parse_mask_file, parse_security_level, validate_security_level, MaskLine, MaskParseError, CMAC_LEN,
+    DEFAULT_ALGORITHM, MAX_MASK_LEN,
+};
+
+/// Entry of the built-in mask table
+struct BuiltinMask {
+    ecu: &'static str,
+    level: u8,
+    mask: &'static [u8],
+    response_len: Option<usize>,
+    key_bits: Option<usize>,
+    algorithm: &'static str,
+    group: Option<&'static str>,
+}
+
+// Built-in mask table, generated from ecu_mask.txt in build.rs
+include!(concat!(env!("OUT_DIR"), "/generated_ecu_mask.rs"));
+
+static BUILTIN_TABLE: Lazy<MaskTable> = Lazy::new(|| {
+    let lines = BUILTIN_MASKS
+        .iter()
+        .enumerate()
+        .map(|(index, builtin)| MaskLine {
+            line: index + 1,
+            ecu: builtin.ecu.to_string(),
+            level: builtin.level,
+            mask: builtin.mask.to_vec(),
+            response_len: builtin.response_len,
+            key_bits: builtin.key_bits,
+            algorithm: builtin.algorithm.to_string(),
+            group: builtin.group.map(str::to_string),
+        })
+        .collect();
+    MaskTable::from_lines(lines).expect("ecu_mask.txt only names known algorithms")
+});
+
+/// Name of an ECU as it appears in the mask table, e.g. `BMS` or `DKM1_L`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EcuType(String);
+
+impl EcuType {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for EcuType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Security access level, i.e. the requestSeed sub-function of UDS SecurityAccess (0x27)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SecurityLevel(u8);
+
+impl SecurityLevel {
+    /// Creates a security level, checking that it is a valid requestSeed sub-function
+    pub fn new(level: u8) -> Result<Self, Box<dyn Error>> {
+        validate_security_level(level)?;
+        Ok(Self(level))
+    }
+
+    /// Returns the requestSeed sub-function
+    pub fn as_u8(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the sendKey sub-function paired with this level
+    pub fn send_key(self) -> u8 {
+        self.0 + 1
+    }
+}
+
+impl FromStr for SecurityLevel {
+    type Err = Box<dyn Error>;
+
+    /// Parses a security level in decimal (`17`) or hex (`0x11`) notation
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(parse_security_level(s.trim())?))
+    }
+}
+
+impl fmt::Display for SecurityLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:02X}", self.0)
+    }
+}
+
+/// One line of a mask file: the mask used for an ECU at a security level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskEntry {
+    pub ecu: EcuType,
+    pub level: SecurityLevel,
+    /// Mask XORed with the seed, its length is the seed length
+    pub mask: Vec<u8>,
+    /// Number of leading bytes of the algorithm output sent as key response
+    pub response_len: usize,
+    /// AES variant, chosen by key length if `None`
+    pub cipher: Option<CmacCipher>,
+    /// Name of the seed-to-key algorithm, see [`crate::crypto::algorithm`]
+    pub algorithm: String,
+    /// Domain of the ECU, e.g. `Powertrain` or `ADAS`
+    pub group: Option<String>,
+}
+
+impl MaskEntry {
+    /// Creates a CMAC entry with a full length response and no domain
+    pub fn new(ecu: EcuType, level: SecurityLevel, mask: Vec<u8>) -> Self {
+        Self {
+            ecu,
+            level,
+            mask,
+            response_len: CMAC_LEN,
+            cipher: None,
+            algorithm: DEFAULT_ALGORITHM.to_string(),
+            group: None,
+        }
+    }
+
+    /// Returns the length of the seed in bytes
+    pub fn seed_len(&self) -> usize {
+        self.mask.len()
+    }
+}
+
+/// Set of masks indexed by ECU and security level
+#[derive(Debug, Clone, Default)]
+pub struct MaskTable {
+    entries: Vec<MaskEntry>,
+}
+
+impl MaskTable {
+    /// Returns the table compiled into the executable from ecu_mask.txt
+    pub fn builtin() -> Self {
+        BUILTIN_TABLE.clone()
+    }
+
+    /// Loads the built-in table, then every `*.txt` file of the config directory
+    /// and finally the given mask files, later entries overriding earlier ones
+    pub fn load(mask_files: &[PathBuf]) -> Result<Self, Box<dyn Error>> {
+        let mut table = Self::builtin();
+
+        if let Some(dir) = config_dir().filter(|dir| dir.is_dir()) {
+            let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+                .map_err(|e| format!("{}: {}", dir.display(), e))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+                .collect();
+            files.sort();
+
+            for file in &files {
+                table.merge(Self::from_file(file)?);
+            }
+        }
+
+        for file in mask_files {
+            table.merge(Self::from_file(file)?);
+        }
+
+        Ok(table)
+    }
+
+    /// Reads a mask file in the `<ECU> <LEVEL> = <MASK>` format of ecu_mask.txt,
+    /// reporting every invalid line as `<path>:<line>: <message>`
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let report = |errors: Vec<MaskParseError>| {
+            errors
+                .iter()
+                .map(|e| format!("{}:{}: {}", path.display(), e.line, e.message))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        Ok(Self::from_lines(parse_mask_file(&content).map_err(report)?).map_err(report)?)
+    }
+
+    /// Parses mask table content in the `<ECU> <LEVEL> = <MASK>` format of ecu_mask.txt,
+    /// reporting every invalid line with its line number
+    pub fn parse(content: &str) -> Result<Self, Box<dyn Error>> {
+        let report = |errors: Vec<MaskParseError>| errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n");
+
+        Ok(Self::from_lines(parse_mask_file(content).map_err(report)?).map_err(report)?)
+    }
+
+    /// Builds the table from parsed lines, checking each line against the
+    /// algorithm it names in [`crypto::algorithms`]
+    fn from_lines(lines: Vec<MaskLine>) -> Result<Self, Vec<MaskParseError>> {
+        let mut table = Self::default();
+        let mut errors = Vec::new();
+        for line in lines {
+            match entry_of(line) {
+                Ok(entry) => table.insert(entry),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        if errors.is_empty() { Ok(table) } else { Err(errors) }
+    }
+
+    /// Adds an entry, replacing any existing entry for the same ECU and security level
+    pub fn insert(&mut self, entry: MaskEntry) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.ecu == entry.ecu && e.level == entry.level)
+        {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Adds all entries of `other`, its entries taking precedence
+    pub fn merge(&mut self, other: MaskTable) {
+        for entry in other.entries {
+            self.insert(entry);
+        }
+    }
+
+    pub fn entries(&self) -> &[MaskEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, ecu: &EcuType, level: SecurityLevel) -> Option<&MaskEntry> {
+        self.entries.iter().find(|e| &e.ecu == ecu && e.level == level)
+    }
+
+    /// Looks up an ECU by its name in the table
+    pub fn ecu(&self, name: &str) -> Option<EcuType> {
+        self.entries
+            .iter()
+            .find(|e| e.ecu.as_str() == name)
+            .map(|e| e.ecu.clone())
+    }
+
+    /// Returns the sorted list of ECU types in the table
+    pub fn ecu_types(&self) -> Vec<EcuType> {
+        let mut ecu_types: Vec<EcuType> = self.entries.iter().map(|e| e.ecu.clone()).collect();
+        ecu_types.sort();
+        ecu_types.dedup();
+        ecu_types
+    }
+
+    /// Returns the domain of an ECU, if any of its entries declares one
+    pub fn group_of(&self, ecu: &EcuType) -> Option<&str> {
+        self.entries
+            .iter()
+            .filter(|e| &e.ecu == ecu)
+            .find_map(|e| e.group.as_deref())
+    }
+
+    /// Returns the ECU types grouped by domain, in order of first appearance of
+    /// each domain. ECUs without a domain are collected in a trailing `None` group.
+    pub fn ecu_groups(&self) -> Vec<(Option<String>, Vec<EcuType>)> {
+        let mut groups: Vec<(Option<String>, Vec<EcuType>)> = Vec::new();
+
+        for ecu in self.ecu_types() {
+            let group = self.group_of(&ecu).map(str::to_string);
+            match groups.iter_mut().find(|(g, _)| *g == group) {
+                Some((_, ecus)) => ecus.push(ecu),
+                None => groups.push((group, vec![ecu])),
+            }
+        }
+
+        // Keep the domains in file order and the ungrouped ECUs last
+        let order = |group: &Option<String>| {
+            self.entries
+                .iter()
+                .position(|e| group.is_some() && e.group == *group)
+                .unwrap_or(usize::MAX)
+        };
+        groups.sort_by_key(|(group, _)| order(group));
+        groups
+    }
+
+    /// Returns the sorted list of security levels in the table
+    pub fn security_levels(&self) -> Vec<SecurityLevel> {
+        let mut levels: Vec<SecurityLevel> = self.entries.iter().map(|e| e.level).collect();
+        levels.sort();
+        levels.dedup();
+        levels
+    }
+
+    /// Returns the sorted list of security levels supported by an ECU
+    pub fn security_levels_for(&self, ecu: &EcuType) -> Vec<SecurityLevel> {
+        let mut levels: Vec<SecurityLevel> = self
+            .entries
+            .iter()
+            .filter(|e| &e.ecu == ecu)
+            .map(|e| e.level)
+            .collect();
+        levels.sort();
+        levels
+    }
+}
+
+/// Creates the entry of a line, resolving the default response length and
+/// checking seed length and cipher with the named algorithm
+fn entry_of(line: MaskLine) -> Result<MaskEntry, MaskParseError> {
+    let error = |message: String| MaskParseError { line: line.line, message };
+
+    let Some(algorithm) = crypto::algorithm(&line.algorithm) else {
+        let names: Vec<&str> = crypto::algorithms().iter().map(|algorithm| algorithm.name()).collect();
+        return Err(error(format!("unknown algorithm `{}`, expected one of {}", line.algorithm, names.join(", "))));
+    };
+    if line.key_bits.is_some() && !algorithm.uses_cipher() {
+        return Err(error(format!("algorithm {} does not use an AES cipher", algorithm.name())));
+    }
+
+    let output_len = algorithm.output_len(line.mask.len()).map_err(error)?;
+    let response_len = line.response_len.unwrap_or(output_len);
+    if response_len > output_len {
+        return Err(error(format!("response length must be 1 to {} bytes, found {}", output_len, response_len)));
+    }
+
+    let mut entry = MaskEntry::new(EcuType::new(line.ecu), SecurityLevel(line.level), line.mask);
+    entry.response_len = response_len;
+    entry.cipher = line.key_bits.and_then(|bits| CmacCipher::from_key_len(bits / 8));
+    entry.algorithm = line.algorithm;
+    entry.group = line.group;
+    Ok(entry)
+}
+
+/// Directory searched for additional mask files at startup
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("seed2cmac"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn security_level_from_str() {
+        let cases: &[(&str, Option<u8>)] = &[
+            ("1", Some(0x01)),
+            (" 0x11 ", Some(0x11)),
+            ("0x5F", Some(0x5F)),
+            ("2", None),
+            ("0x43", None),
+            ("0x7F", None),
+            ("level1", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(text.parse::<SecurityLevel>().ok().map(SecurityLevel::as_u8), *expected, "{:?}", text);
+        }
+        assert_eq!(SecurityLevel::new(0x11).unwrap().to_string(), "0x11");
+        assert_eq!(SecurityLevel::new(0x11).unwrap().send_key(), 0x12);
+    }
+}
@@ -0,0 +1,413 @@
+// Parser for the `<ECU> <LEVEL> = <MASK> [seed=<N>] [response=<N>] [cipher=<AES>] [algo=<NAME>]` mask file format.
+//
+// This file is shared with build.rs through `#[path]`, so it must only depend on std.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Maximum length of a mask in bytes
+pub const MAX_MASK_LEN: usize = 64;
+
+/// Length of a full CMAC in bytes
+pub const CMAC_LEN: usize = 16;
+
+/// Algorithm of entries without an `algo=` attribute
+pub const DEFAULT_ALGORITHM: &str = "cmac";
+
+/// A validated line of a mask file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskLine {
+    pub line: usize,
+    pub ecu: String,
+    pub level: u8,
+    /// Mask XORed with the seed, its length is the seed length
+    pub mask: Vec<u8>,
+    /// Number of leading bytes of the algorithm output sent as key response,
+    /// the whole output if absent
+    pub response_len: Option<usize>,
+    /// AES key size in bits from the `cipher=` attribute, chosen by key length if absent
+    pub key_bits: Option<usize>,
+    /// Name of the seed-to-key algorithm, checked against the algorithms of the
+    /// library when the mask table is built
+    pub algorithm: String,
+    /// Domain of the ECU, from the last `[Group]` section header before the line
+    pub group: Option<String>,
+}
+
+/// An error found on one line of a mask file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl MaskParseError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self { line, message: message.into() }
+    }
+}
+
+impl fmt::Display for MaskParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for MaskParseError {}
+
+/// Parses and validates mask file content.
+///
+/// Blank lines and lines starting with `#` are ignored. A `[Group]` line starts
+/// a section whose ECUs belong to that domain, e.g. `[Powertrain]`. Every other
+/// line must have the form `<ECU> <LEVEL> = <MASK>`, optionally followed by
+/// `seed=<N>` to declare the seed length, `response=<N>` to truncate the CMAC to
+/// N bytes, `cipher=aes128|aes192|aes256` to fix the AES variant and `algo=<NAME>`
+/// to select a seed-to-key algorithm other than CMAC, e.g.
+/// `IMU 1 = 1b32b04b seed=4 response=4`. All errors of the file are collected
+/// instead of stopping at the first one.
+pub fn parse_mask_file(content: &str) -> Result<Vec<MaskLine>, Vec<MaskParseError>> {
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    let mut group: Option<String> = None;
+
+    // First line of each (ecu, level) pair and of each normalized ECU name
+    let mut seen_entries: HashMap<(String, u8), usize> = HashMap::new();
+    let mut seen_names: HashMap<String, (String, usize)> = HashMap::new();
+
+    for (index, text) in content.lines().enumerate() {
+        let line = index + 1;
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = text.strip_prefix('[') {
+            match header.strip_suffix(']').map(str::trim) {
+                Some(name) if !name.is_empty() => group = Some(name.to_string()),
+                _ => errors.push(MaskParseError::new(line, format!("invalid section header `{}`", text))),
+            }
+            continue;
+        }
+
+        let parsed = match parse_line(line, text, group.as_deref()) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        };
+
+        let key = (parsed.ecu.clone(), parsed.level);
+        if let Some(first) = seen_entries.get(&key) {
+            errors.push(MaskParseError::new(
+                line,
+                format!("duplicate entry for ECU {} level {}, first defined on line {}", parsed.ecu, parsed.level, first),
+            ));
+            continue;
+        }
+        seen_entries.insert(key, line);
+
+        match seen_names.get(&normalized_name(&parsed.ecu)) {
+            Some((other, first)) if *other != parsed.ecu => {
+                errors.push(MaskParseError::new(
+                    line,
+                    format!("ECU name {} collides with {} defined on line {}", parsed.ecu, other, first),
+                ));
+                continue;
+            }
+            Some(_) => {}
+            None => {
+                seen_names.insert(normalized_name(&parsed.ecu), (parsed.ecu.clone(), line));
+            }
+        }
+
+        lines.push(parsed);
+    }
+
+    if errors.is_empty() { Ok(lines) } else { Err(errors) }
+}
+
+fn parse_line(line: usize, text: &str, group: Option<&str>) -> Result<MaskLine, MaskParseError> {
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    if parts.len() < 4 || parts[2] != "=" {
+        return Err(MaskParseError::new(
+            line,
+            format!("expected `<ECU> <LEVEL> = <MASK>`, found `{}`", text),
+        ));
+    }
+
+    let ecu = parts[0];
+    if !ecu.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(MaskParseError::new(line, format!("invalid ECU name `{}`", ecu)));
+    }
+
+    let level = parse_security_level(parts[1]).map_err(|e| MaskParseError::new(line, e))?;
+
+    let mask = parts[3];
+    if let Some((position, c)) = mask.chars().enumerate().find(|(_, c)| !c.is_ascii_hexdigit()) {
+        return Err(MaskParseError::new(
+            line,
+            format!("invalid hex character `{}` at position {} of mask", c, position + 1),
+        ));
+    }
+    if !mask.len().is_multiple_of(2) || mask.is_empty() || mask.len() > MAX_MASK_LEN * 2 {
+        return Err(MaskParseError::new(
+            line,
+            format!(
+                "mask must be an even number of hex characters up to {}, found {}",
+                MAX_MASK_LEN * 2,
+                mask.len()
+            ),
+        ));
+    }
+
+    let mask: Vec<u8> = (0..mask.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&mask[i..i + 2], 16).expect("validated hex"))
+        .collect();
+
+    let mut seed_len = None;
+    let mut response_len = None;
+    let mut key_bits = None;
+    let mut algorithm = None;
+    for attribute in &parts[4..] {
+        let (name, value) = attribute
+            .split_once('=')
+            .ok_or_else(|| MaskParseError::new(line, format!("expected `<NAME>=<VALUE>`, found `{}`", attribute)))?;
+
+        if name == "algo" {
+            if value.is_empty() || !value.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+                return Err(MaskParseError::new(line, format!("invalid algorithm name `{}`", value)));
+            }
+            if algorithm.replace(value).is_some() {
+                return Err(MaskParseError::new(line, "attribute `algo` given twice"));
+            }
+            continue;
+        }
+
+        let (slot, value) = match name {
+            "seed" | "response" => {
+                let slot = if name == "seed" { &mut seed_len } else { &mut response_len };
+                let value = value
+                    .parse::<usize>()
+                    .map_err(|_| MaskParseError::new(line, format!("invalid length `{}` for {}", value, name)))?;
+                (slot, value)
+            }
+            "cipher" => {
+                let value = parse_cipher(value)
+                    .ok_or_else(|| MaskParseError::new(line, format!("unknown cipher `{}`, expected aes128, aes192 or aes256", value)))?;
+                (&mut key_bits, value)
+            }
+            _ => return Err(MaskParseError::new(line, format!("unknown attribute `{}`", name))),
+        };
+        if slot.replace(value).is_some() {
+            return Err(MaskParseError::new(line, format!("attribute `{}` given twice", name)));
+        }
+    }
+
+    // The seed is XORed byte by byte with the mask, so both have the same length.
+    // Without `seed=` the mask must be a full 16 bytes, so a dropped byte pair is caught.
+    let seed_len = seed_len.unwrap_or(CMAC_LEN);
+    if seed_len != mask.len() {
+        return Err(MaskParseError::new(
+            line,
+            format!("mask is {} bytes but seed length is {}", mask.len(), seed_len),
+        ));
+    }
+
+    if response_len == Some(0) {
+        return Err(MaskParseError::new(line, "response length must be at least 1 byte"));
+    }
+
+    Ok(MaskLine {
+        line,
+        ecu: ecu.to_string(),
+        level,
+        mask,
+        response_len,
+        key_bits,
+        algorithm: algorithm.unwrap_or(DEFAULT_ALGORITHM).to_string(),
+        group: group.map(str::to_string),
+    })
+}
+
+/// Parses the `cipher=` attribute, e.g. `aes256` or `AES-256`, into the AES key size in bits
+fn parse_cipher(text: &str) -> Option<usize> {
+    match text.replace('-', "").to_ascii_lowercase().as_str() {
+        "aes128" => Some(128),
+        "aes192" => Some(192),
+        "aes256" => Some(256),
+        _ => None,
+    }
+}
+
+/// Parses a security level in decimal (`17`) or hex (`0x11`) notation and checks
+/// that it is a UDS SecurityAccess requestSeed sub-function
+pub fn parse_security_level(text: &str) -> Result<u8, String> {
+    let level = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => text.parse::<u8>(),
+    }
+    .map_err(|_| format!("invalid security level `{}`", text))?;
+
+    validate_security_level(level)?;
+    Ok(level)
+}
+
+/// Checks that `level` is a requestSeed sub-function of UDS SecurityAccess (0x27):
+/// an odd value in 0x01-0x41 or in the system supplier specific range 0x61-0x7D,
+/// or the ISO 26021-2 value 0x5F
+pub fn validate_security_level(level: u8) -> Result<(), String> {
+    let valid = match level {
+        0x01..=0x41 | 0x61..=0x7D => level % 2 == 1,
+        0x5F => true,
+        _ => false,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("security level 0x{:02X} is not a UDS requestSeed sub-function", level))
+    }
+}
+
+/// ECU names that are equal once underscores are removed and case is ignored
+/// (e.g. `DKM1_L` and `DKM1L`) are too easily confused and are rejected
+fn normalized_name(ecu: &str) -> String {
+    ecu.chars().filter(|c| *c != '_').map(|c| c.to_ascii_lowercase()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the errors of `content`, which must fail to parse
+    fn errors(content: &str) -> Vec<MaskParseError> {
+        parse_mask_file(content).unwrap_err()
+    }
+
+    #[test]
+    fn mask_without_seed_length_must_be_a_full_cmac() {
+        let errors = errors("BMS 1 = 00112233445566778899aabbccddee00\nESP 1 = 00112233445566778899aabbccddee\n");
+        assert_eq!(errors, [MaskParseError::new(2, "mask is 15 bytes but seed length is 16")]);
+
+        let lines = parse_mask_file("ESP 1 = 00112233445566778899aabbccddee seed=15").unwrap();
+        assert_eq!(lines[0].mask.len(), 15);
+    }
+
+    const MASK: &str = "00112233445566778899aabbccddeeff";
+
+    /// Returns the message of the single error of a one-line file, checking its line number
+    fn error(text: &str) -> String {
+        let errors = errors(&format!("# header\n\n{}\n", text));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].line, 3);
+        errors[0].message.clone()
+    }
+
+    #[test]
+    fn malformed_lines() {
+        assert_eq!(error("BMS 1 00112233"), "expected `<ECU> <LEVEL> = <MASK>`, found `BMS 1 00112233`");
+        assert_eq!(error("BMS 1 ="), "expected `<ECU> <LEVEL> = <MASK>`, found `BMS 1 =`");
+        assert_eq!(error(&format!("BMS-2 1 = {MASK}")), "invalid ECU name `BMS-2`");
+        assert_eq!(error("[Powertrain"), "invalid section header `[Powertrain`");
+    }
+
+    #[test]
+    fn invalid_masks() {
+        assert_eq!(error("BMS 1 = 0011223g"), "invalid hex character `g` at position 8 of mask");
+        assert_eq!(error("BMS 1 = 0x112233"), "invalid hex character `x` at position 2 of mask");
+        assert_eq!(error("BMS 1 = 001"), "mask must be an even number of hex characters up to 128, found 3");
+        assert_eq!(error(&format!("BMS 1 = {}", "00".repeat(65))), "mask must be an even number of hex characters up to 128, found 130");
+        assert_eq!(error(&format!("BMS 1 = {MASK} seed=4")), "mask is 16 bytes but seed length is 4");
+    }
+
+    #[test]
+    fn invalid_attributes() {
+        assert_eq!(error(&format!("BMS 1 = {MASK} seed")), "expected `<NAME>=<VALUE>`, found `seed`");
+        assert_eq!(error(&format!("BMS 1 = {MASK} crc=8")), "unknown attribute `crc`");
+        assert_eq!(error(&format!("BMS 1 = {MASK} response=4 response=8")), "attribute `response` given twice");
+        assert_eq!(error(&format!("BMS 1 = {MASK} algo=cmac algo=cmac")), "attribute `algo` given twice");
+        assert_eq!(error(&format!("BMS 1 = {MASK} seed=x")), "invalid length `x` for seed");
+        assert_eq!(error(&format!("BMS 1 = {MASK} response=0")), "response length must be at least 1 byte");
+        assert_eq!(error(&format!("BMS 1 = {MASK} algo=ROT_13")), "invalid algorithm name `ROT_13`");
+        assert_eq!(error(&format!("BMS 1 = {MASK} cipher=des")), "unknown cipher `des`, expected aes128, aes192 or aes256");
+    }
+
+    #[test]
+    fn duplicate_entries_and_colliding_names() {
+        let errors = errors(&format!("DKM1_L 1 = {MASK}\nDKM1_L 0x01 = {MASK}\nDKM1L 3 = {MASK}\ndkm1_l 5 = {MASK}\n"));
+        assert_eq!(
+            errors,
+            [
+                MaskParseError::new(2, "duplicate entry for ECU DKM1_L level 1, first defined on line 1"),
+                MaskParseError::new(3, "ECU name DKM1L collides with DKM1_L defined on line 1"),
+                MaskParseError::new(4, "ECU name dkm1_l collides with DKM1_L defined on line 1"),
+            ]
+        );
+
+        // The same ECU at another level is no collision
+        assert_eq!(parse_mask_file(&format!("DKM1_L 1 = {MASK}\nDKM1_L 3 = {MASK}")).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn all_errors_are_reported_in_one_pass() {
+        let content = format!("BMS 1 = {MASK}\nBMS 2 = {MASK}\n\nESP 1 = 00zz\nESP 3 = {MASK}\nESP 3 = {MASK}\nIMU\n");
+        let errors = errors(&content);
+        let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [2, 4, 6, 7]);
+        assert_eq!(errors[0].message, "security level 0x02 is not a UDS requestSeed sub-function");
+        assert_eq!(errors[3].to_string(), "line 7: expected `<ECU> <LEVEL> = <MASK>`, found `IMU`");
+    }
+
+    #[test]
+    fn security_level_notations() {
+        let cases: &[(&str, Result<u8, &str>)] = &[
+            ("1", Ok(0x01)),
+            ("17", Ok(0x11)),
+            ("0x11", Ok(0x11)),
+            ("0X11", Ok(0x11)),
+            ("0x5f", Ok(0x5F)),
+            ("95", Ok(0x5F)),
+            ("0x7D", Ok(0x7D)),
+            ("011", Ok(0x0B)),
+            ("", Err("invalid security level ``")),
+            ("0x", Err("invalid security level `0x`")),
+            ("x11", Err("invalid security level `x11`")),
+            ("256", Err("invalid security level `256`")),
+            ("-1", Err("invalid security level `-1`")),
+            ("0x2", Err("security level 0x02 is not a UDS requestSeed sub-function")),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_security_level(text), expected.map_err(str::to_string), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn security_level_ranges() {
+        let cases: &[(u8, bool)] = &[
+            (0x00, false),
+            (0x01, true),
+            // sendKey sub-functions are even
+            (0x02, false),
+            (0x40, false),
+            (0x41, true),
+            (0x43, false),
+            (0x5D, false),
+            (0x5E, false),
+            // ISO 26021-2 end of life activation
+            (0x5F, true),
+            (0x60, false),
+            (0x61, true),
+            (0x7D, true),
+            (0x7F, false),
+            (0x81, false),
+            (0xFF, false),
+        ];
+        for &(level, valid) in cases {
+            assert_eq!(validate_security_level(level).is_ok(), valid, "0x{:02X}", level);
+        }
+        // Every odd level in the ISO reserved range 0x43-0x5D is rejected
+        assert!((0x43..=0x5D).step_by(2).all(|level| validate_security_level(level).is_err()));
+    }
+}
@@ -1,31 +1,583 @@
-use std::error::Error;
-use hex;
-
-use crate::cli;
-use crate::crypto;
-
-pub fn hex_string_to_bytes(hex_str: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-    if hex_str.len() % 2 != 0 {
-        return Err("Invalid hex string".into());
-    }
-
-    if !hex_str.chars().all(|c| c.is_ascii_hexdigit()) {
-        return Err("Invalid hex string".into());
-    }
-
-    let mut bytes = vec![0; hex_str.len() / 2];
-    hex::decode_to_slice(hex_str, &mut bytes)?;
-
-    Ok(bytes)
-}
-
-pub fn bytes_to_hex_string(bytes: &[u8]) -> String {
-    hex::encode(bytes)
-}
-
-pub fn process(arg: &cli::CliArg) -> Result<Vec<u8>, Box<dyn Error>> {
-    let seed = hex_string_to_bytes(&arg.seed)?;
-    let key = hex_string_to_bytes(&arg.key)?;
-    let cmac = crypto::calculate_cmac(&key, &seed)?;
-    Ok(cmac)
-}
+use std::fmt;
+use std::net::{TcpListener, UdpSocket};
+use std::thread;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use seed2cmac::audit::{AuditEvent, AuditLog};
+use seed2cmac::batch;
+use seed2cmac::client::{self, UnlockReport};
+use seed2cmac::doip::{self, DoIpClient, DoIpGateway, VehicleIdentification};
+use seed2cmac::simulator::{SimulatedEcu, SimulatorConfig};
+use seed2cmac::tcp::TcpTransport;
+use seed2cmac::keystore::{is_reference, resolve_key, KeyStore};
+use seed2cmac::service::KeyService;
+use seed2cmac::uds::NegativeResponse;
+use seed2cmac::util::{bytes_to_hex_string, parse_hex_input, SecretBytes, SecretString};
+use seed2cmac::{find_matching_entries, MaskTable, SecurityLevel, SeedKeyCalculator};
+
+use crate::cli;
+
+/// VIN the simulated DoIP gateway reports in vehicle identification
+const SIMULATED_VIN: &str = "SEED2CMACSIMULATE";
+
+/// Logical address of the simulated ECU behind the DoIP gateway, unless given
+const SIMULATED_ECU_ADDRESS: u16 = 0x1001;
+
+/// Time to wait for the TCP connection and for each response of the ECU
+const TCP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Error classes of the headless mode, each mapped to its own exit code
+#[derive(Debug)]
+pub enum ProcessError {
+    UnknownEcu(String),
+    UnsupportedLevel(String, SecurityLevel, Vec<SecurityLevel>),
+    MaskNotFound(String, SecurityLevel),
+    InvalidSeed(usize),
+    InvalidKey(String),
+    Calculation(String),
+    MaskFile(String),
+    InvalidResponse,
+    Communication(String),
+    Rejected(NegativeResponse),
+    Batch(String),
+    /// Number of failed rows and rows of a batch file
+    BatchRowsFailed(usize, usize),
+    Service(String),
+    KeyStore(String),
+    Audit(String),
+}
+
+impl ProcessError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ProcessError::UnknownEcu(_) => 3,
+            ProcessError::UnsupportedLevel(..) => 4,
+            ProcessError::MaskNotFound(..) => 5,
+            ProcessError::InvalidSeed(_) => 6,
+            ProcessError::InvalidKey(_) => 7,
+            ProcessError::Calculation(_) => 8,
+            ProcessError::MaskFile(_) => 9,
+            ProcessError::InvalidResponse => 10,
+            ProcessError::Communication(_) => 11,
+            ProcessError::Rejected(_) => 12,
+            ProcessError::Batch(_) => 13,
+            ProcessError::BatchRowsFailed(..) => 14,
+            ProcessError::Service(_) => 15,
+            ProcessError::KeyStore(_) => 16,
+            ProcessError::Audit(_) => 17,
+        }
+    }
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::UnknownEcu(ecu) => write!(f, "Unknown ECU type: {}", ecu),
+            ProcessError::UnsupportedLevel(ecu, level, supported) => {
+                let supported: Vec<String> = supported.iter().map(|l| l.to_string()).collect();
+                write!(
+                    f,
+                    "ECU {} does not support security level {}, supported levels: {}",
+                    ecu,
+                    level,
+                    supported.join(", ")
+                )
+            }
+            ProcessError::MaskNotFound(ecu, level) => {
+                write!(f, "No mask configured for ECU {} and security level {}", ecu, level)
+            }
+            ProcessError::InvalidSeed(len) => {
+                write!(f, "Invalid seed: must be a {} character hex string", len * 2)
+            }
+            ProcessError::InvalidKey(e) => write!(f, "Invalid key: {}", e),
+            ProcessError::Calculation(e) => write!(f, "Key calculation failed: {}", e),
+            ProcessError::MaskFile(e) => write!(f, "Invalid mask file: {}", e),
+            ProcessError::InvalidResponse => write!(f, "Invalid response: not a hex string"),
+            ProcessError::Communication(e) => write!(f, "Communication with the ECU failed: {}", e),
+            ProcessError::Rejected(nrc) => write!(f, "ECU rejected the request: {}", nrc),
+            ProcessError::Batch(e) => write!(f, "Batch file failed: {}", e),
+            ProcessError::BatchRowsFailed(failed, rows) => write!(f, "{} of {} batch rows failed", failed, rows),
+            ProcessError::Service(e) => write!(f, "Key service failed: {}", e),
+            ProcessError::KeyStore(e) => write!(f, "Key store failed: {}", e),
+            ProcessError::Audit(e) => write!(f, "Audit log failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {}
+
+/// Applies the mask XOR and the algorithm of the ECU entry to the command line
+/// arguments, recording the response in the audit log if one is given
+pub fn process(
+    table: &MaskTable,
+    arg: &cli::HeadlessArg,
+    keys: Option<&KeyStore>,
+    audit: Option<&AuditLog>,
+) -> Result<SecretBytes, ProcessError> {
+    let (calculator, seed, key) = prepare(table, arg, keys)?;
+
+    let response = calculator
+        .calculate(&seed, &key)
+        .map_err(|e| ProcessError::Calculation(e.to_string()))?;
+    if let Some(audit) = audit {
+        record_audit(audit, "headless", &calculator, &seed, &response)?;
+    }
+    Ok(response)
+}
+
+/// Records a calculated key response in the audit log
+fn record_audit(audit: &AuditLog, source: &str, calculator: &SeedKeyCalculator, seed: &[u8], response: &[u8]) -> Result<(), ProcessError> {
+    audit
+        .record(&AuditEvent::new(source, &calculator.ecu().to_string(), calculator.level(), seed, response))
+        .map_err(|e| ProcessError::Audit(format!("{}: {}", audit.path().display(), e)))
+}
+
+/// Result of checking a recorded key response
+#[derive(Debug)]
+pub enum Verification {
+    Match,
+    /// The response does not match, with the ECU, level and mask of every
+    /// table entry that would have produced it
+    Mismatch(Vec<(String, SecurityLevel, Vec<u8>)>),
+}
+
+/// Checks the recorded key response against the one expected for the command
+/// line arguments, recording the checked response in the audit log if one is given
+pub fn verify(
+    table: &MaskTable,
+    arg: &cli::HeadlessArg,
+    response: &str,
+    keys: Option<&KeyStore>,
+    audit: Option<&AuditLog>,
+) -> Result<Verification, ProcessError> {
+    let (calculator, seed, key) = prepare(table, arg, keys)?;
+    let response = parse_hex_input(response).map_err(|_| ProcessError::InvalidResponse)?;
+
+    let matches = calculator
+        .verify(&seed, &key, &response)
+        .map_err(|e| ProcessError::Calculation(e.to_string()))?;
+    if let Some(audit) = audit {
+        record_audit(audit, "headless-verify", &calculator, &seed, &response)?;
+    }
+    if matches {
+        return Ok(Verification::Match);
+    }
+
+    let candidates = find_matching_entries(table, &seed, &key, &response)
+        .into_iter()
+        .map(|entry| (entry.ecu.to_string(), entry.level, entry.mask.clone()))
+        .collect();
+    Ok(Verification::Mismatch(candidates))
+}
+
+/// Looks up the calculator of the ECU entry and validates seed and key
+fn prepare(
+    table: &MaskTable,
+    arg: &cli::HeadlessArg,
+    keys: Option<&KeyStore>,
+) -> Result<(SeedKeyCalculator, SecretBytes, SecretBytes), ProcessError> {
+    let calculator = calculator_for(table, &arg.ecu, arg.level)?;
+
+    let seed = parse_hex_input(&arg.seed)
+        .ok()
+        .filter(|seed| seed.len() == calculator.seed_len())
+        .ok_or(ProcessError::InvalidSeed(calculator.seed_len()))?;
+    let key = parse_key(&calculator, &arg.key, keys)?;
+
+    Ok((calculator, seed, key))
+}
+
+fn calculator_for(table: &MaskTable, ecu_name: &str, level: SecurityLevel) -> Result<SeedKeyCalculator, ProcessError> {
+    let ecu = table.ecu(ecu_name).ok_or_else(|| ProcessError::UnknownEcu(ecu_name.to_string()))?;
+    let supported = table.security_levels_for(&ecu);
+    if !supported.contains(&level) {
+        return Err(ProcessError::UnsupportedLevel(ecu_name.to_string(), level, supported));
+    }
+
+    SeedKeyCalculator::from_table(table, &ecu, level).map_err(|_| ProcessError::MaskNotFound(ecu_name.to_string(), level))
+}
+
+/// Decodes a hex key or looks up an `@alias` in the key store
+fn parse_key(calculator: &SeedKeyCalculator, key: &str, keys: Option<&KeyStore>) -> Result<SecretBytes, ProcessError> {
+    let key = resolve_cli_key(key, keys)?;
+    calculator.check_key(&key).map_err(|e| ProcessError::InvalidKey(e.to_string()))?;
+    Ok(key)
+}
+
+/// Unlocks the ECU over the link of the command line arguments, recording the
+/// key sent in the audit log if one is given
+pub fn unlock(
+    table: &MaskTable,
+    arg: &cli::UnlockArg,
+    keys: Option<&KeyStore>,
+    audit: Option<&AuditLog>,
+) -> Result<UnlockReport, ProcessError> {
+    let calculator = calculator_for(table, &arg.ecu, arg.level)?;
+    let key = parse_key(&calculator, &arg.key, keys)?;
+
+    let mut transport = match &arg.link {
+        cli::Link::Can { interface, tx_id, rx_id } => open_isotp(interface, *tx_id, *rx_id)?,
+        cli::Link::Tcp(addr) => Box::new(
+            TcpTransport::connect(addr, TCP_TIMEOUT).map_err(|e| ProcessError::Communication(format!("{}: {}", addr, e)))?,
+        ),
+        cli::Link::DoIp { addr, tester_address, ecu_address } => {
+            let ecu_address = match ecu_address {
+                Some(address) => *address,
+                None => {
+                    doip::identify(addr, TCP_TIMEOUT)
+                        .map_err(|e| ProcessError::Communication(format!("vehicle identification at {}: {}", addr, e)))?
+                        .logical_address
+                }
+            };
+            Box::new(
+                DoIpClient::connect(addr, *tester_address, ecu_address, TCP_TIMEOUT)
+                    .map_err(|e| ProcessError::Communication(format!("{}: {}", addr, e)))?,
+            )
+        }
+    };
+    let report = client::unlock(transport.as_mut(), &calculator, &key).map_err(|e| match e.downcast::<NegativeResponse>() {
+        Ok(nrc) => ProcessError::Rejected(*nrc),
+        Err(e) => ProcessError::Communication(e.to_string()),
+    })?;
+    if let (Some(audit), Some(response)) = (audit, &report.key) {
+        record_audit(audit, "unlock", &calculator, &report.seed, response)?;
+    }
+    Ok(report)
+}
+
+/// Opens an ISO-TP connection sending on `tx_id` and receiving on `rx_id`
+#[cfg(target_os = "linux")]
+fn open_isotp(interface: &str, tx_id: u32, rx_id: u32) -> Result<Box<dyn client::UdsTransport>, ProcessError> {
+    use seed2cmac::can::SocketCan;
+    use seed2cmac::isotp::{IsoTp, IsoTpConfig};
+
+    let can = SocketCan::open(interface).map_err(|e| ProcessError::Communication(format!("{}: {}", interface, e)))?;
+    can.set_filter(&[rx_id]).map_err(|e| ProcessError::Communication(e.to_string()))?;
+    Ok(Box::new(IsoTp::new(can, IsoTpConfig::new(tx_id, rx_id))))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_isotp(_interface: &str, _tx_id: u32, _rx_id: u32) -> Result<Box<dyn client::UdsTransport>, ProcessError> {
+    Err(ProcessError::Communication(String::from("SocketCAN is only available on Linux")))
+}
+
+/// Creates the simulated ECU of the command line arguments
+pub fn simulator(table: &MaskTable, arg: &cli::SimulateArg, keys: Option<&KeyStore>) -> Result<SimulatedEcu, ProcessError> {
+    let ecu = table.ecu(&arg.ecu).ok_or_else(|| ProcessError::UnknownEcu(arg.ecu.clone()))?;
+    let key = resolve_cli_key(&arg.key, keys)?;
+    let config = SimulatorConfig { max_attempts: arg.max_attempts, delay: arg.delay };
+    SimulatedEcu::new(table, &ecu, key, config).map_err(|e| ProcessError::InvalidKey(e.to_string()))
+}
+
+/// Runs the headless mode and returns the process exit code
+pub fn run(mask_files: &[PathBuf], key_store: Option<&Path>, audit_log: Option<&Path>, arg: &cli::HeadlessArg) -> i32 {
+    let table = match MaskTable::load(mask_files) {
+        Ok(table) => table,
+        Err(e) => return report(ProcessError::MaskFile(e.to_string())),
+    };
+    let keys = match open_key_store(key_store, is_reference(&arg.key)) {
+        Ok(keys) => keys,
+        Err(err) => return report(err),
+    };
+
+    let audit = match open_audit_log(audit_log) {
+        Ok(audit) => audit,
+        Err(err) => return report(err),
+    };
+
+    if let Some(response) = &arg.response {
+        return match verify(&table, arg, response, keys.as_ref(), Some(&audit)) {
+            Ok(Verification::Match) => {
+                println!("match");
+                0
+            }
+            Ok(Verification::Mismatch(candidates)) => {
+                println!("mismatch");
+                for (ecu, level, mask) in candidates {
+                    println!("candidate: {} {} = {}", ecu, level, bytes_to_hex_string(&mask));
+                }
+                1
+            }
+            Err(err) => report(err),
+        };
+    }
+
+    match process(&table, arg, keys.as_ref(), Some(&audit)) {
+        Ok(cmac) => {
+            println!("{}", arg.format.format(&cmac, arg.level).as_str());
+            0
+        }
+        Err(err) => report(err),
+    }
+}
+
+fn report(err: ProcessError) -> i32 {
+    eprintln!("error: {}", err);
+    err.exit_code()
+}
+
+/// Runs the security access on the CAN bus and returns the process exit code
+pub fn run_unlock(mask_files: &[PathBuf], key_store: Option<&Path>, audit_log: Option<&Path>, arg: &cli::UnlockArg) -> i32 {
+    let result = MaskTable::load(mask_files)
+        .map_err(|e| ProcessError::MaskFile(e.to_string()))
+        .and_then(|table| Ok((table, open_key_store(key_store, is_reference(&arg.key))?, open_audit_log(audit_log)?)))
+        .and_then(|(table, keys, audit)| unlock(&table, arg, keys.as_ref(), Some(&audit)));
+
+    match result {
+        Ok(report) => {
+            println!("seed: {}", bytes_to_hex_string(&report.seed));
+            match report.key {
+                Some(key) => {
+                    println!("key: {}", arg.format.format(&key, arg.level).as_str());
+                    println!("unlocked");
+                }
+                None => println!("already unlocked"),
+            }
+            0
+        }
+        Err(err) => report(err),
+    }
+}
+
+/// Runs the simulated ECU until it fails and returns the process exit code,
+/// printing every request and response
+pub fn run_simulator(mask_files: &[PathBuf], key_store: Option<&Path>, arg: &cli::SimulateArg) -> i32 {
+    let mut ecu = match MaskTable::load(mask_files)
+        .map_err(|e| ProcessError::MaskFile(e.to_string()))
+        .and_then(|table| Ok((table, open_key_store(key_store, is_reference(&arg.key))?)))
+        .and_then(|(table, keys)| simulator(&table, arg, keys.as_ref()))
+    {
+        Ok(ecu) => ecu,
+        Err(err) => return report(err),
+    };
+
+    let mut log = |request: &[u8], response: &[u8]| {
+        println!("> {}", bytes_to_hex_string(request));
+        println!("< {}", bytes_to_hex_string(response));
+    };
+
+    let result = match &arg.link {
+        cli::Link::Can { interface, tx_id, rx_id } => {
+            println!("simulating {} on {}", ecu.ecu(), interface);
+            // The ECU receives the requests of the tester and answers on its response identifier
+            open_isotp(interface, *rx_id, *tx_id).and_then(|mut transport| {
+                ecu.serve(transport.as_mut(), &mut log).map_err(|e| ProcessError::Communication(e.to_string()))
+            })
+        }
+        cli::Link::Tcp(addr) => TcpListener::bind(addr)
+            .map_err(|e| ProcessError::Communication(format!("{}: {}", addr, e)))
+            .and_then(|listener| {
+                println!("simulating {} on {}", ecu.ecu(), addr);
+                ecu.serve_tcp(&listener, &mut log).map_err(|e| ProcessError::Communication(e.to_string()))
+            }),
+        cli::Link::DoIp { addr, ecu_address, .. } => serve_doip(ecu, addr, ecu_address.unwrap_or(SIMULATED_ECU_ADDRESS), &mut log),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(err) => report(err),
+    }
+}
+
+/// Serves the simulated ECU behind a loopback DoIP gateway whose logical address
+/// is the one of the ECU, answering vehicle identification on the same address
+fn serve_doip(ecu: SimulatedEcu, addr: &str, ecu_address: u16, log: &mut dyn FnMut(&[u8], &[u8])) -> Result<(), ProcessError> {
+    let bind_error = |e: std::io::Error| ProcessError::Communication(format!("{}: {}", addr, e));
+    let listener = TcpListener::bind(addr).map_err(bind_error)?;
+    let socket = UdpSocket::bind(addr).map_err(bind_error)?;
+
+    let identification = VehicleIdentification {
+        vin: SIMULATED_VIN.to_string(),
+        logical_address: ecu_address,
+        eid: [0x02, 0, 0, 0, 0, 0x01],
+        gid: [0; 6],
+    };
+    println!("simulating {} at DoIP address 0x{:04X} on {}", ecu.ecu(), ecu_address, addr);
+
+    let mut gateway = DoIpGateway::new(identification.clone());
+    gateway.add_ecu(ecu_address, ecu);
+    thread::spawn(move || doip::serve_identification(&socket, &identification));
+    gateway
+        .serve_tcp(&listener, &mut |_, request, response| log(request, response))
+        .map_err(|e| ProcessError::Communication(e.to_string()))
+}
+
+/// Calculates the keys of the batch file and returns the process exit code
+pub fn run_batch(mask_files: &[PathBuf], key_store: Option<&Path>, audit_log: Option<&Path>, arg: &cli::BatchArg) -> i32 {
+    let table = match MaskTable::load(mask_files) {
+        Ok(table) => table,
+        Err(e) => return report(ProcessError::MaskFile(e.to_string())),
+    };
+    // Rows with `@alias` keys need the key store, unlocked if given or if the default key is an alias
+    let needed = key_store.is_some() || arg.key.as_deref().is_some_and(is_reference);
+    let keys = match open_key_store(key_store, needed) {
+        Ok(keys) => keys,
+        Err(err) => return report(err),
+    };
+
+    let audit = match open_audit_log(audit_log) {
+        Ok(audit) => audit,
+        Err(err) => return report(err),
+    };
+
+    let output = arg.output.clone().unwrap_or_else(|| batch::output_path(&arg.input));
+    match batch::run(&table, &arg.input, &output, arg.key.as_deref(), keys.as_ref(), Some(&audit), arg.format) {
+        Ok(summary) => {
+            println!("{} rows, {} failed, written to {}", summary.rows, summary.failed, output.display());
+            if summary.failed == 0 {
+                0
+            } else {
+                report(ProcessError::BatchRowsFailed(summary.failed, summary.rows))
+            }
+        }
+        Err(e) => report(ProcessError::Batch(e.to_string())),
+    }
+}
+
+/// Serves key calculations over HTTP until the server fails and returns the process exit code
+pub fn run_serve(mask_files: &[PathBuf], key_store: Option<&Path>, audit_log: Option<&Path>, arg: &cli::ServeArg) -> i32 {
+    let table = match MaskTable::load(mask_files) {
+        Ok(table) => table,
+        Err(e) => return report(ProcessError::MaskFile(e.to_string())),
+    };
+    let audit = match open_audit_log(audit_log) {
+        Ok(audit) => audit,
+        Err(err) => return report(err),
+    };
+
+    let mut service = KeyService::new(table, arg.token.clone()).with_audit_log(audit);
+    // Requests with `@alias` keys need the key store, unlocked if given or if the default one exists
+    let needed = key_store.is_some() || KeyStore::default_path().is_some_and(|path| path.exists());
+    match open_key_store(key_store, needed) {
+        Ok(Some(keys)) => service = service.with_key_store(keys),
+        Ok(None) => {}
+        Err(err) => return report(err),
+    }
+    println!("serving on http://{}", arg.addr);
+    if arg.token.is_none() && !is_loopback(&arg.addr) {
+        eprintln!("warning: serving on {} without --token", arg.addr);
+    }
+
+    match service.serve(&arg.addr, &mut |method, path, status| println!("{} {} {}", method, path, status)) {
+        Ok(()) => 0,
+        Err(e) => report(ProcessError::Service(format!("{}: {}", arg.addr, e))),
+    }
+}
+
+fn is_loopback(addr: &str) -> bool {
+    addr.parse::<std::net::SocketAddr>().is_ok_and(|addr| addr.ip().is_loopback()) || addr.starts_with("localhost:")
+}
+
+/// Decodes a hex key or looks up an `@alias` in the key store
+fn resolve_cli_key(key: &str, keys: Option<&KeyStore>) -> Result<SecretBytes, ProcessError> {
+    if !is_reference(key) {
+        return parse_hex_input(key).map_err(|e| ProcessError::InvalidKey(e.to_string()));
+    }
+    resolve_key(key, keys).map_err(|e| ProcessError::KeyStore(e.to_string()))
+}
+
+/// Returns the key store file given with `--key-store` or the default one
+fn key_store_path(key_store: Option<&Path>) -> Result<PathBuf, ProcessError> {
+    key_store
+        .map(Path::to_path_buf)
+        .or_else(KeyStore::default_path)
+        .ok_or_else(|| ProcessError::KeyStore(String::from("no config directory for the key store")))
+}
+
+/// Returns the audit log given with `--audit-log` or the default one
+fn open_audit_log(audit_log: Option<&Path>) -> Result<AuditLog, ProcessError> {
+    audit_log
+        .map(Path::to_path_buf)
+        .or_else(AuditLog::default_path)
+        .map(AuditLog::new)
+        .ok_or_else(|| ProcessError::Audit(String::from("no config directory for the audit log")))
+}
+
+/// Checks the hash chain of the audit log and returns the process exit code
+pub fn run_verify_audit_log(audit_log: Option<&Path>) -> i32 {
+    let result = open_audit_log(audit_log).and_then(|audit| {
+        let count = audit.verify().map_err(|e| ProcessError::Audit(format!("{}: {}", audit.path().display(), e)))?;
+        Ok((audit, count))
+    });
+
+    match result {
+        Ok((audit, count)) => {
+            println!("{}: {} entries, chain intact", audit.path().display(), count);
+            0
+        }
+        Err(err) => report(err),
+    }
+}
+
+/// Reads the passphrase from `SEED2CMAC_PASSPHRASE` or prompts for it without echo
+fn read_passphrase(prompt: &str) -> Result<SecretString, ProcessError> {
+    if let Ok(passphrase) = std::env::var("SEED2CMAC_PASSPHRASE") {
+        return Ok(passphrase.into());
+    }
+    rpassword::prompt_password(prompt).map(SecretString::from).map_err(|e| ProcessError::KeyStore(format!("cannot read the passphrase: {}", e)))
+}
+
+/// Unlocks the key store if it is `needed` to resolve `@alias` keys
+fn open_key_store(key_store: Option<&Path>, needed: bool) -> Result<Option<KeyStore>, ProcessError> {
+    if !needed {
+        return Ok(None);
+    }
+
+    let path = key_store_path(key_store)?;
+    let passphrase = read_passphrase("Key store passphrase: ")?;
+    KeyStore::open(&path, &passphrase).map(Some).map_err(|e| ProcessError::KeyStore(e.to_string()))
+}
+
+/// Adds, removes or lists key store aliases and returns the process exit code
+pub fn run_key_store(key_store: Option<&Path>, arg: &cli::KeyStoreArg) -> i32 {
+    match manage_key_store(key_store, arg) {
+        Ok(()) => 0,
+        Err(err) => report(err),
+    }
+}
+
+fn manage_key_store(key_store: Option<&Path>, arg: &cli::KeyStoreArg) -> Result<(), ProcessError> {
+    let path = key_store_path(key_store)?;
+    let is_new = !path.exists();
+    let passphrase = read_passphrase("Key store passphrase: ")?;
+    if is_new && std::env::var("SEED2CMAC_PASSPHRASE").is_err() && passphrase != read_passphrase("Repeat the passphrase: ")? {
+        return Err(ProcessError::KeyStore(String::from("the passphrases do not match")));
+    }
+    let mut keys = KeyStore::open_or_new(&path, &passphrase).map_err(|e| ProcessError::KeyStore(e.to_string()))?;
+
+    match arg {
+        cli::KeyStoreArg::List => {
+            for alias in keys.aliases() {
+                println!("{}", alias);
+            }
+            return Ok(());
+        }
+        cli::KeyStoreArg::Add(alias) => {
+            let key = read_secret_key()?;
+            keys.insert(alias, key).map_err(|e| ProcessError::KeyStore(e.to_string()))?;
+            println!("added @{}", alias);
+        }
+        cli::KeyStoreArg::Remove(alias) => {
+            if !keys.remove(alias) {
+                return Err(ProcessError::KeyStore(format!("unknown key alias @{}", alias)));
+            }
+            println!("removed @{}", alias);
+        }
+    }
+
+    keys.save(&path, &passphrase).map_err(|e| ProcessError::KeyStore(e.to_string()))
+}
+
+/// Reads the hex key from stdin if piped, otherwise prompts for it without echo
+fn read_secret_key() -> Result<SecretBytes, ProcessError> {
+    let key = SecretString::from(if io::stdin().is_terminal() {
+        rpassword::prompt_password("Key (hex): ").map_err(|e| ProcessError::KeyStore(e.to_string()))?
+    } else {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line).map_err(|e| ProcessError::KeyStore(e.to_string()))?;
+        line
+    });
+    parse_hex_input(&key).map_err(|e| ProcessError::InvalidKey(e.to_string()))
+}
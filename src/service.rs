@@ -0,0 +1,196 @@
+//! Local HTTP/JSON service exposing the seed to key calculation to other tools.
+//!
+//! `POST /v1/key` calculates a key response and `GET /v1/ecus` lists the ECUs of
+//! the mask table with their security levels.
+
+use std::error::Error;
+use std::io::Read;
+use std::sync::Mutex;
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::audit::{AuditEvent, AuditLog};
+use crate::batch::{self, BatchRow};
+use crate::crypto::constant_time_eq;
+use crate::keystore::KeyStore;
+use crate::mask::{MaskTable, SecurityLevel};
+use crate::util::{parse_hex_input, SecretString};
+
+/// Address the service binds to unless configured otherwise
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8731";
+
+/// Largest request body accepted
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+/// Requests handled at the same time, so that a client that stalls while
+/// sending its body only holds up its own worker
+const WORKERS: usize = 4;
+
+/// Called with method, path and status of every request
+pub type RequestLog = dyn FnMut(&str, &str, u16) + Send;
+
+/// Body of `POST /v1/key`, `level` as a number or text such as `"0x11"` and
+/// `key` as hex or an `@alias` of the key store
+#[derive(Debug, Deserialize)]
+struct KeyRequest {
+    ecu: String,
+    level: serde_json::Value,
+    seed: String,
+    key: SecretString,
+}
+
+#[derive(Debug, Serialize)]
+struct EcuInfo {
+    ecu: String,
+    group: Option<String>,
+    levels: Vec<String>,
+}
+
+/// Status code and JSON body of a response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: serde_json::Value) -> Self {
+        Self { status, body: body.to_string() }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, json!({ "error": message.into() }))
+    }
+}
+
+/// Answers key and ECU list requests from a mask table, optionally requiring a bearer token
+#[derive(Debug)]
+pub struct KeyService {
+    table: MaskTable,
+    token: Option<String>,
+    keys: Option<KeyStore>,
+    audit: Option<AuditLog>,
+}
+
+impl KeyService {
+    pub fn new(table: MaskTable, token: Option<String>) -> Self {
+        Self { table, token, keys: None, audit: None }
+    }
+
+    /// Resolves `@alias` keys of requests from the unlocked key store
+    pub fn with_key_store(mut self, keys: KeyStore) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Records every calculated key in the audit log, answering 500 if it cannot be written
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Answers a request, `authorization` being the value of its Authorization header
+    pub fn handle(&self, method: &str, path: &str, authorization: Option<&str>, body: &[u8]) -> Response {
+        if let Some(token) = &self.token {
+            let given = authorization.and_then(|value| value.strip_prefix("Bearer ")).unwrap_or("");
+            if !constant_time_eq(given.trim().as_bytes(), token.as_bytes()) {
+                return Response::error(401, "Missing or invalid bearer token");
+            }
+        }
+
+        match (method, path.split('?').next().unwrap_or(path)) {
+            ("POST", "/v1/key") => self.key(body),
+            ("GET", "/v1/ecus") => self.ecus(),
+            (_, "/v1/key" | "/v1/ecus") => Response::error(405, "Method not allowed"),
+            _ => Response::error(404, "Not found"),
+        }
+    }
+
+    fn key(&self, body: &[u8]) -> Response {
+        let request = match serde_json::from_slice::<KeyRequest>(body) {
+            Ok(request) => request,
+            Err(e) => return Response::error(400, format!("Invalid request: {}", e)),
+        };
+
+        let level = match request.level {
+            serde_json::Value::String(level) => level,
+            level => level.to_string(),
+        };
+        let row = BatchRow { line: 0, ecu: request.ecu, level, seed: request.seed, key: Some(request.key) };
+        let response = match batch::calculate_row(&self.table, &row, None, self.keys.as_ref()) {
+            Ok(response) => response,
+            Err(e) => return Response::error(422, e.to_string()),
+        };
+
+        if let Some(audit) = &self.audit {
+            // The row was calculated, so its level and seed are valid
+            let level = row.level.parse::<SecurityLevel>().expect("calculated level");
+            let seed = parse_hex_input(&row.seed).expect("calculated seed");
+            if let Err(e) = audit.record(&AuditEvent::new("service", &row.ecu, level, &seed, &response)) {
+                return Response::error(500, format!("Audit log failed: {}", e));
+            }
+        }
+        Response::json(200, json!({ "ecu": row.ecu, "level": row.level, "response": hex::encode(response) }))
+    }
+
+    fn ecus(&self) -> Response {
+        let ecus: Vec<EcuInfo> = self
+            .table
+            .ecu_types()
+            .into_iter()
+            .map(|ecu| EcuInfo {
+                group: self.table.group_of(&ecu).map(str::to_string),
+                levels: self.table.security_levels_for(&ecu).iter().map(|level| level.to_string()).collect(),
+                ecu: ecu.to_string(),
+            })
+            .collect();
+        Response::json(200, json!({ "ecus": ecus }))
+    }
+
+    /// Serves HTTP requests on `addr` with a few worker threads until the server
+    /// fails, calling `log` with method, path and status of every request
+    pub fn serve(&self, addr: &str, log: &mut RequestLog) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let server = tiny_http::Server::http(addr)?;
+        let log = Mutex::new(log);
+        thread::scope(|scope| {
+            for _ in 0..WORKERS {
+                scope.spawn(|| {
+                    for request in server.incoming_requests() {
+                        self.respond(request, &log);
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
+
+    /// Reads the body of the request and answers it
+    fn respond(&self, mut request: tiny_http::Request, log: &Mutex<&mut RequestLog>) {
+        let method = request.method().to_string();
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .map(|header| header.value.to_string());
+
+        let mut body = Vec::new();
+        let read = request.as_reader().take(MAX_BODY_LEN as u64 + 1).read_to_end(&mut body);
+        let response = match read {
+            Ok(_) if body.len() > MAX_BODY_LEN => Response::error(413, "Request body too large"),
+            Ok(_) => self.handle(&method, request.url(), authorization.as_deref(), &body),
+            Err(e) => Response::error(400, e.to_string()),
+        };
+
+        if let Ok(mut log) = log.lock() {
+            log(&method, request.url(), response.status);
+        }
+        let content_type = tiny_http::Header::from_bytes("Content-Type", "application/json").expect("valid header");
+        let http_response = tiny_http::Response::from_string(response.body)
+            .with_status_code(response.status)
+            .with_header(content_type);
+        // A client that went away only loses its own response
+        let _ = request.respond(http_response);
+    }
+}
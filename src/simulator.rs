@@ -0,0 +1,226 @@
+//! Simulated UDS ECU answering DiagnosticSessionControl (0x10), SecurityAccess
+//! (0x27) and TesterPresent (0x3E), for testing tools and training without hardware.
+
+use std::error::Error;
+use std::io;
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+use rand::RngCore;
+
+use crate::calculator::SeedKeyCalculator;
+use crate::client::UdsTransport;
+use crate::mask::{EcuType, MaskTable, SecurityLevel};
+use crate::tcp::TcpTransport;
+use crate::uds::{NEGATIVE_RESPONSE, SECURITY_ACCESS};
+use crate::util::SecretBytes;
+
+const DEFAULT_SESSION: u8 = 0x01;
+
+/// Bit of the sub-function asking the ECU not to send a positive response
+const SUPPRESS_POSITIVE_RESPONSE: u8 = 0x80;
+
+/// Time the simulator waits for a request before waiting again
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Attempt counter and delay timer of the simulated ECU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulatorConfig {
+    /// Invalid keys accepted before the delay timer starts, answered with NRC 0x36
+    pub max_attempts: u32,
+    /// Time after the last allowed invalid key during which seeds are refused with NRC 0x37
+    pub delay: Duration,
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        Self { max_attempts: 3, delay: Duration::from_secs(10) }
+    }
+}
+
+/// A UDS ECU whose security levels and masks come from a mask table
+#[derive(Debug)]
+pub struct SimulatedEcu {
+    ecu: EcuType,
+    calculators: Vec<SeedKeyCalculator>,
+    key: SecretBytes,
+    config: SimulatorConfig,
+    session: u8,
+    /// Level and seed of the last requestSeed, answered by the next sendKey
+    pending_seed: Option<(SecurityLevel, Vec<u8>)>,
+    unlocked: Option<SecurityLevel>,
+    failed_attempts: u32,
+    delay_until: Option<Instant>,
+}
+
+impl SimulatedEcu {
+    /// Creates an ECU supporting every security level of `ecu` in the table,
+    /// accepting the keys calculated with `key`
+    pub fn new(table: &MaskTable, ecu: &EcuType, key: SecretBytes, config: SimulatorConfig) -> Result<Self, Box<dyn Error>> {
+        let calculators = table
+            .security_levels_for(ecu)
+            .into_iter()
+            .map(|level| SeedKeyCalculator::from_table(table, ecu, level))
+            .collect::<Result<Vec<_>, _>>()?;
+        if calculators.is_empty() {
+            return Err(format!("Unknown ECU type: {}", ecu).into());
+        }
+        for calculator in &calculators {
+            calculator.check_key(&key)?;
+        }
+
+        Ok(Self {
+            ecu: ecu.clone(),
+            calculators,
+            key,
+            config,
+            session: DEFAULT_SESSION,
+            pending_seed: None,
+            unlocked: None,
+            failed_attempts: 0,
+            delay_until: None,
+        })
+    }
+
+    pub fn ecu(&self) -> &EcuType {
+        &self.ecu
+    }
+
+    /// Returns the unlocked security level, if any
+    pub fn unlocked(&self) -> Option<SecurityLevel> {
+        self.unlocked
+    }
+
+    /// Answers a request, empty if the request sets the
+    /// suppressPosRspMsgIndicationBit and the response is positive
+    pub fn handle(&mut self, request: &[u8]) -> Vec<u8> {
+        match request {
+            [sid @ (0x10 | 0x3E), sub_function, rest @ ..] if sub_function & SUPPRESS_POSITIVE_RESPONSE != 0 => {
+                let request = [&[*sid, sub_function & !SUPPRESS_POSITIVE_RESPONSE][..], rest].concat();
+                let response = self.respond(&request);
+                if response.first() == Some(&NEGATIVE_RESPONSE) { response } else { Vec::new() }
+            }
+            _ => self.respond(request),
+        }
+    }
+
+    fn respond(&mut self, request: &[u8]) -> Vec<u8> {
+        match request {
+            [0x10, session] => self.session_control(*session),
+            [SECURITY_ACCESS, sub_function, data @ ..] => self.security_access(*sub_function, data),
+            [0x3E, 0x00] => vec![0x7E, 0x00],
+            [sid @ (0x10 | SECURITY_ACCESS | 0x3E), ..] => negative(*sid, 0x13),
+            [sid, ..] => negative(*sid, 0x11),
+            [] => negative(0x00, 0x13),
+        }
+    }
+
+    fn session_control(&mut self, session: u8) -> Vec<u8> {
+        if !(0x01..=0x03).contains(&session) {
+            return negative(0x10, 0x12);
+        }
+
+        // A session change locks the ECU again
+        self.session = session;
+        self.pending_seed = None;
+        self.unlocked = None;
+
+        // P2 50 ms, P2* 5000 ms
+        vec![0x50, session, 0x00, 0x32, 0x01, 0xF4]
+    }
+
+    fn security_access(&mut self, sub_function: u8, data: &[u8]) -> Vec<u8> {
+        if self.session == DEFAULT_SESSION {
+            return negative(SECURITY_ACCESS, 0x7F);
+        }
+
+        let request_seed = sub_function % 2 == 1;
+        let level = if request_seed { sub_function } else { sub_function.wrapping_sub(1) };
+        let Some(calculator) = self.calculators.iter().find(|c| c.level().as_u8() == level) else {
+            return negative(SECURITY_ACCESS, 0x12);
+        };
+
+        if self.delay_until.is_some_and(|until| Instant::now() < until) {
+            return negative(SECURITY_ACCESS, 0x37);
+        }
+
+        let level = calculator.level();
+        if request_seed {
+            // An unlocked level is reported with an all-zero seed
+            let seed = if self.unlocked == Some(level) {
+                vec![0; calculator.seed_len()]
+            } else {
+                let seed = random_seed(calculator.seed_len());
+                self.pending_seed = Some((level, seed.clone()));
+                seed
+            };
+            return [&[0x67, sub_function][..], &seed].concat();
+        }
+
+        let Some(seed) = self.pending_seed.take().filter(|(l, _)| *l == level).map(|(_, seed)| seed) else {
+            return negative(SECURITY_ACCESS, 0x24);
+        };
+
+        if calculator.verify(&seed, &self.key, data).unwrap_or(false) {
+            self.unlocked = Some(level);
+            self.failed_attempts = 0;
+            return vec![0x67, sub_function];
+        }
+
+        self.failed_attempts += 1;
+        if self.failed_attempts >= self.config.max_attempts {
+            self.failed_attempts = 0;
+            self.delay_until = Some(Instant::now() + self.config.delay);
+            negative(SECURITY_ACCESS, 0x36)
+        } else {
+            negative(SECURITY_ACCESS, 0x35)
+        }
+    }
+
+    /// Answers requests on the transport until the peer disconnects, calling
+    /// `log` with every request and response. Receive timeouts are ignored.
+    pub fn serve(&mut self, transport: &mut dyn UdsTransport, log: &mut dyn FnMut(&[u8], &[u8])) -> Result<(), Box<dyn Error>> {
+        loop {
+            let request = match transport.receive(IDLE_TIMEOUT) {
+                Ok(request) => request,
+                Err(e) => match e.downcast_ref::<io::Error>().map(io::Error::kind) {
+                    Some(io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => continue,
+                    Some(io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset) => return Ok(()),
+                    _ => return Err(e),
+                },
+            };
+
+            let response = self.handle(&request);
+            log(&request, &response);
+            if !response.is_empty() {
+                transport.send(&response)?;
+            }
+        }
+    }
+
+    /// Accepts TCP connections one after another and serves each of them,
+    /// keeping attempt counter and delay timer across connections
+    pub fn serve_tcp(&mut self, listener: &TcpListener, log: &mut dyn FnMut(&[u8], &[u8])) -> Result<(), Box<dyn Error>> {
+        for stream in listener.incoming() {
+            let mut transport = TcpTransport::new(stream?);
+            // Lock the ECU again for each tester, like a power cycle
+            self.session_control(DEFAULT_SESSION);
+            // A misbehaving tester only ends its own connection
+            let _ = self.serve(&mut transport, log);
+        }
+        Ok(())
+    }
+}
+
+fn negative(sid: u8, nrc: u8) -> Vec<u8> {
+    vec![NEGATIVE_RESPONSE, sid, nrc]
+}
+
+/// Returns a random seed that is not all zero, which would mean unlocked
+fn random_seed(len: usize) -> Vec<u8> {
+    let mut seed = vec![0; len];
+    while seed.iter().all(|b| *b == 0) {
+        rand::thread_rng().fill_bytes(&mut seed);
+    }
+    seed
+}
@@ -0,0 +1,85 @@
+//! UDS messages over a TCP stream, each prefixed with its length as a 32-bit big-endian integer.
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::client::UdsTransport;
+use crate::isotp::MAX_PAYLOAD_LEN;
+
+/// Length prefixed UDS messages on a TCP connection
+#[derive(Debug)]
+pub struct TcpTransport {
+    stream: TcpStream,
+    /// Bytes of the next messages received before a receive timed out
+    pending: Vec<u8>,
+}
+
+impl TcpTransport {
+    /// Connects to `addr`, waiting up to `timeout` for the connection
+    pub fn connect(addr: &str, timeout: Duration) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no address for {}", addr)))?;
+        Ok(Self::new(TcpStream::connect_timeout(&addr, timeout)?))
+    }
+
+    pub fn new(stream: TcpStream) -> Self {
+        Self { stream, pending: Vec::new() }
+    }
+}
+
+impl UdsTransport for TcpTransport {
+    fn send(&mut self, message: &[u8]) -> Result<(), Box<dyn Error>> {
+        if message.is_empty() || message.len() > MAX_PAYLOAD_LEN {
+            return Err(format!("UDS message must be 1 to {} bytes, got {}", MAX_PAYLOAD_LEN, message.len()).into());
+        }
+
+        let mut frame = (message.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(message);
+        self.stream.write_all(&frame)?;
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Result<Vec<u8>, Box<dyn Error>> {
+        // A message cut off by the timeout stays pending for the next receive, so the
+        // stream never loses track of the message boundaries
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(len) = self.pending.first_chunk::<4>().map(|len| u32::from_be_bytes(*len) as usize) {
+                if len == 0 || len > MAX_PAYLOAD_LEN {
+                    self.pending.clear();
+                    return Err(format!("Invalid UDS message length {}", len).into());
+                }
+                if self.pending.len() >= 4 + len {
+                    let message = self.pending[4..4 + len].to_vec();
+                    self.pending.drain(..4 + len);
+                    return Ok(message);
+                }
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(timed_out(timeout).into());
+            }
+            self.stream.set_read_timeout(Some(remaining))?;
+            let mut buf = [0; 4096];
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by the peer").into()),
+                Ok(n) => self.pending.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                // Unix reports a read timeout as `WouldBlock`
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    return Err(timed_out(timeout).into());
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+fn timed_out(timeout: Duration) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, format!("Timeout waiting for a UDS message after {} ms", timeout.as_millis()))
+}
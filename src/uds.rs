@@ -0,0 +1,112 @@
+//! Framing of UDS SecurityAccess (0x27) messages as defined by ISO 14229-1.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::mask::SecurityLevel;
+use crate::util::parse_hex_input;
+
+/// Service identifier of SecurityAccess
+pub const SECURITY_ACCESS: u8 = 0x27;
+
+/// Service identifier of a positive SecurityAccess response
+pub const SECURITY_ACCESS_RESPONSE: u8 = SECURITY_ACCESS + 0x40;
+
+/// Service identifier of a negative response
+pub const NEGATIVE_RESPONSE: u8 = 0x7F;
+
+/// Seed received in a positive requestSeed response `67 <level> <seed>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeedResponse {
+    pub level: SecurityLevel,
+    pub seed: Vec<u8>,
+}
+
+/// Negative response `7F <SID> <NRC>` to a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegativeResponse {
+    pub sid: u8,
+    pub nrc: u8,
+}
+
+impl fmt::Display for NegativeResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "negative response to 0x{:02X}: NRC 0x{:02X} ({})", self.sid, self.nrc, nrc_name(self.nrc))
+    }
+}
+
+impl Error for NegativeResponse {}
+
+/// Returns the ISO 14229-1 name of a negative response code
+pub fn nrc_name(nrc: u8) -> &'static str {
+    match nrc {
+        0x10 => "generalReject",
+        0x11 => "serviceNotSupported",
+        0x12 => "subFunctionNotSupported",
+        0x13 => "incorrectMessageLengthOrInvalidFormat",
+        0x21 => "busyRepeatRequest",
+        0x22 => "conditionsNotCorrect",
+        0x24 => "requestSequenceError",
+        0x31 => "requestOutOfRange",
+        0x33 => "securityAccessDenied",
+        0x35 => "invalidKey",
+        0x36 => "exceededNumberOfAttempts",
+        0x37 => "requiredTimeDelayNotExpired",
+        0x78 => "requestCorrectlyReceivedResponsePending",
+        0x7E => "subFunctionNotSupportedInActiveSession",
+        0x7F => "serviceNotSupportedInActiveSession",
+        _ => "unknown",
+    }
+}
+
+/// Parses a message copied from a trace tool, e.g. `67 01 A1 B2` or `0x67, 0x01, …`
+pub fn parse_hex_frame(input: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(parse_hex_input(input)?.to_vec())
+}
+
+/// Builds the requestSeed request `27 <level>`
+pub fn request_seed(level: SecurityLevel) -> Vec<u8> {
+    vec![SECURITY_ACCESS, level.as_u8()]
+}
+
+/// Builds the sendKey request `27 <level + 1> <key>` answering the seed of `level`
+pub fn send_key(level: SecurityLevel, key: &[u8]) -> Vec<u8> {
+    let mut request = vec![SECURITY_ACCESS, level.send_key()];
+    request.extend_from_slice(key);
+    request
+}
+
+/// Parses a positive requestSeed response `67 <level> <seed>`, reporting a
+/// negative response `7F 27 <NRC>` as [`NegativeResponse`]
+pub fn parse_seed_response(frame: &[u8]) -> Result<SeedResponse, Box<dyn Error>> {
+    check_negative_response(frame)?;
+
+    match frame {
+        [SECURITY_ACCESS_RESPONSE, level, seed @ ..] => {
+            let level = SecurityLevel::new(*level)?;
+            if seed.is_empty() {
+                return Err("Seed response without seed".into());
+            }
+            Ok(SeedResponse { level, seed: seed.to_vec() })
+        }
+        [sid, ..] => Err(format!("Not a SecurityAccess response: SID 0x{:02X}", sid).into()),
+        [] => Err("Empty response".into()),
+    }
+}
+
+/// Parses the response to a sendKey request of `level`, `67 <level + 1>` if the key was accepted
+pub fn parse_send_key_response(frame: &[u8], level: SecurityLevel) -> Result<(), Box<dyn Error>> {
+    check_negative_response(frame)?;
+
+    match frame {
+        [SECURITY_ACCESS_RESPONSE, sub_function, ..] if *sub_function == level.send_key() => Ok(()),
+        _ => Err(format!("Unexpected sendKey response: {:02X?}", frame).into()),
+    }
+}
+
+fn check_negative_response(frame: &[u8]) -> Result<(), NegativeResponse> {
+    match frame {
+        [NEGATIVE_RESPONSE, sid, nrc, ..] => Err(NegativeResponse { sid: *sid, nrc: *nrc }),
+        _ => Ok(()),
+    }
+}
@@ -1,8 +1,173 @@
-use hex;
 use std::error::Error;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
 
-pub fn hex_string_to_bytes(hex_str: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-    if hex_str.len() != 32 {
+use base64ct::{Base64, Base64Unpadded, Encoding};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+use crate::mask::SecurityLevel;
+use crate::uds;
+
+/// Key, seed, mask or response bytes, wiped when dropped and redacted in `Debug`
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretBytes(Vec<u8>);
+
+impl SecretBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Shortens the bytes to `len`, wiping the removed tail
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.0.len() {
+            self.0[len..].zeroize();
+            self.0.truncate(len);
+        }
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes([REDACTED; {}])", self.0.len())
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl DerefMut for SecretBytes {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl AsRef<[u8]> for SecretBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for SecretBytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl PartialEq<[u8]> for SecretBytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&[u8]> for SecretBytes {
+    fn eq(&self, other: &&[u8]) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<Vec<u8>> for SecretBytes {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        &self.0 == other
+    }
+}
+
+impl<const N: usize> PartialEq<[u8; N]> for SecretBytes {
+    fn eq(&self, other: &[u8; N]) -> bool {
+        self.0 == other
+    }
+}
+
+/// Hex or text input holding secret material, wiped when dropped and redacted
+/// in `Debug`
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString([REDACTED])")
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for SecretString {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl PartialEq<str> for SecretString {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for SecretString {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+pub fn hex_string_to_bytes(hex_str: &str) -> Result<SecretBytes, Box<dyn Error>> {
+    if hex_str.is_empty() || !hex_str.len().is_multiple_of(2) {
         return Err("Invalid hex string".into());
     }
 
@@ -10,24 +175,295 @@ pub fn hex_string_to_bytes(hex_str: &str) -> Result<Vec<u8>, Box<dyn Error>> {
         return Err("Invalid hex string".into());
     }
 
-    let mut bytes = vec![0; hex_str.len() / 2];
+    let mut bytes = SecretBytes::new(vec![0; hex_str.len() / 2]);
     hex::decode_to_slice(hex_str, &mut bytes)?;
 
     Ok(bytes)
 }
 
+/// Characters separating the bytes of pasted hex input, besides whitespace
+const HEX_SEPARATORS: &str = ",;:-_|\"'";
+
+/// Reason hex input could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexInputError {
+    /// The input holds no hex digits
+    Empty,
+    /// A character that is neither a hex digit nor a separator or prefix, at
+    /// its position in the input counting characters from 1
+    InvalidChar { position: usize, character: char },
+    /// The input holds an odd number of hex digits
+    OddLength(usize),
+    /// The input starts with `base64:` but is not valid base64
+    InvalidBase64,
+}
+
+impl fmt::Display for HexInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexInputError::Empty => write!(f, "no hex digits"),
+            HexInputError::InvalidChar { position, character } => {
+                write!(f, "invalid character '{}' at position {}", character, position)
+            }
+            HexInputError::OddLength(digits) => write!(f, "odd number of hex digits: {}", digits),
+            HexInputError::InvalidBase64 => write!(f, "invalid base64"),
+        }
+    }
+}
+
+impl Error for HexInputError {}
+
+/// Normalizes hex input copied from trace tools, source code or the clipboard
+/// to contiguous lowercase hex digits
+///
+/// Accepts contiguous digits, bytes separated by whitespace or `,;:-_|`, `0x`
+/// and `\x` prefixes, C array literals such as `uint8_t seed[] = {0xA1, 0xB2};`,
+/// lists in brackets and standard base64. Base64 must end in `=` padding or
+/// start with `base64:`, so that a mistyped hex digit is reported rather than
+/// the input being read as base64.
+pub fn normalize_hex_input(input: &str) -> Result<SecretString, HexInputError> {
+    let trimmed = input.trim();
+    if let Some(base64) = trimmed.strip_prefix("base64:") {
+        return base64_to_hex(base64.trim()).ok_or(HexInputError::InvalidBase64);
+    }
+
+    match hex_digits(input) {
+        Err(err @ HexInputError::InvalidChar { .. }) if trimmed.ends_with('=') => base64_to_hex(trimmed).ok_or(err),
+        result => result,
+    }
+}
+
+/// Decodes hex input in any of the forms accepted by [`normalize_hex_input`]
+pub fn parse_hex_input(input: &str) -> Result<SecretBytes, HexInputError> {
+    let hex = normalize_hex_input(input)?;
+    let mut bytes = SecretBytes::new(vec![0; hex.len() / 2]);
+    hex::decode_to_slice(hex.as_str(), &mut bytes).expect("normalized hex");
+    Ok(bytes)
+}
+
+/// Result of checking hex input while it is typed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HexInputCheck {
+    /// Number of hex digits read so far
+    pub digits: usize,
+    /// Characters that are neither hex digits nor separators or prefixes, with
+    /// their position in the input counting characters from 1
+    pub invalid: Vec<(usize, char)>,
+}
+
+impl HexInputCheck {
+    /// Returns whether the input is valid hex of `len` bytes
+    pub fn is_complete(&self, len: usize) -> bool {
+        self.invalid.is_empty() && self.digits == len * 2
+    }
+}
+
+/// Checks incomplete hex input in any of the forms accepted by
+/// [`normalize_hex_input`], counting its digits and collecting every invalid
+/// character rather than stopping at the first
+pub fn check_hex_input(input: &str) -> HexInputCheck {
+    if let Ok(hex) = normalize_hex_input(input) {
+        return HexInputCheck { digits: hex.len(), invalid: Vec::new() };
+    }
+    let (hex, invalid) = scan_hex(input);
+    HexInputCheck { digits: hex.len(), invalid }
+}
+
+/// Collects the hex digits of the input, only looking inside the braces of a C
+/// array literal or the brackets of a list
+fn hex_digits(input: &str) -> Result<SecretString, HexInputError> {
+    let (hex, invalid) = scan_hex(input);
+    if let Some(&(position, character)) = invalid.first() {
+        return Err(HexInputError::InvalidChar { position, character });
+    }
+
+    match hex.len() {
+        0 => Err(HexInputError::Empty),
+        len if !len.is_multiple_of(2) => Err(HexInputError::OddLength(len)),
+        _ => Ok(hex),
+    }
+}
+
+/// Returns the hex digits of the input and the invalid characters skipped
+fn scan_hex(input: &str) -> (SecretString, Vec<(usize, char)>) {
+    let (start, end) = array_body(input);
+    let mut chars = input.chars().enumerate().take(end).skip(start).peekable();
+    // Reserved up front so that no partial copy is left behind by reallocating; a
+    // prefix takes at least as many characters as the `0` it may add to a token
+    let mut hex = SecretString(String::with_capacity(input.len()));
+    let mut token = SecretString(String::with_capacity(input.len()));
+    let mut prefixed = false;
+    let mut invalid = Vec::new();
+
+    while let Some((index, c)) = chars.next() {
+        let prefix_follows = matches!(chars.peek(), Some((_, 'x' | 'X')));
+        if c == '0' && prefix_follows && token.is_empty() && !prefixed {
+            chars.next();
+            prefixed = true;
+        } else if c == '\\' && prefix_follows {
+            end_token(&mut hex, &mut token, &mut prefixed);
+            chars.next();
+            prefixed = true;
+        } else if c.is_ascii_hexdigit() {
+            token.0.push(c.to_ascii_lowercase());
+        } else if c.is_whitespace() || HEX_SEPARATORS.contains(c) {
+            end_token(&mut hex, &mut token, &mut prefixed);
+        } else {
+            invalid.push((index + 1, c));
+        }
+    }
+    end_token(&mut hex, &mut token, &mut prefixed);
+    (hex, invalid)
+}
+
+/// Appends the digits of a token, `0xA` standing for the byte `0x0A`
+fn end_token(hex: &mut SecretString, token: &mut SecretString, prefixed: &mut bool) {
+    if *prefixed && token.len() == 1 {
+        hex.0.push('0');
+    }
+    hex.0.push_str(token);
+    token.0.zeroize();
+    *prefixed = false;
+}
+
+/// Returns the character range between `{` and `}`, or `[` and `]` if the input
+/// starts with one, and the whole input otherwise
+fn array_body(input: &str) -> (usize, usize) {
+    let len = input.chars().count();
+    let open = input.chars().position(|c| c == '{').map(|index| (index, '}')).or_else(|| {
+        let trimmed = input.chars().position(|c| !c.is_whitespace())?;
+        (input.chars().nth(trimmed) == Some('[')).then_some((trimmed, ']'))
+    });
+
+    match open {
+        Some((index, close)) => {
+            let end = input.chars().skip(index + 1).position(|c| c == close).map_or(len, |offset| index + 1 + offset);
+            (index + 1, end)
+        }
+        None => (0, len),
+    }
+}
+
+/// Decodes standard base64, padded or not, to hex, `None` if the input is not base64
+fn base64_to_hex(input: &str) -> Option<SecretString> {
+    let mut buffer = SecretBytes::new(vec![0; input.len()]);
+    let len = match Base64::decode(input, &mut buffer) {
+        Ok(bytes) => bytes.len(),
+        Err(_) => Base64Unpadded::decode(input, &mut buffer).ok()?.len(),
+    };
+    buffer.truncate(len);
+    (len > 0).then(|| bytes_to_secret_hex(&buffer))
+}
+
 pub fn bytes_to_hex_string(bytes: &[u8]) -> String {
     hex::encode(bytes)
 }
 
-pub fn xor_bytes(a: &Vec<u8>, b: &Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+/// Hex encodes secret bytes into a string that is wiped when dropped
+pub fn bytes_to_secret_hex(bytes: &[u8]) -> SecretString {
+    SecretString(hex::encode(bytes))
+}
+
+/// Text format of a calculated key response, as expected by the tool it is pasted into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    /// Contiguous lowercase hex, e.g. `a1b2c3`
+    #[default]
+    Hex,
+    /// Contiguous uppercase hex, e.g. `A1B2C3`
+    Upper,
+    /// Uppercase bytes separated by spaces, e.g. `A1 B2 C3`
+    Spaced,
+    /// Comma separated `0x` bytes as in a C array, e.g. `0xA1, 0xB2, 0xC3`
+    CList,
+    /// The whole UDS sendKey request, e.g. `27 02 A1 B2 C3` at level 1
+    Uds,
+    /// Standard base64 with padding, e.g. `obLD`
+    Base64,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 6] = [
+        OutputFormat::Hex,
+        OutputFormat::Upper,
+        OutputFormat::Spaced,
+        OutputFormat::CList,
+        OutputFormat::Uds,
+        OutputFormat::Base64,
+    ];
+
+    /// Returns the name used on the command line, e.g. `c-list`
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Hex => "hex",
+            OutputFormat::Upper => "upper",
+            OutputFormat::Spaced => "spaced",
+            OutputFormat::CList => "c-list",
+            OutputFormat::Uds => "uds",
+            OutputFormat::Base64 => "base64",
+        }
+    }
+
+    /// Formats the key response calculated at the security level, which only
+    /// the UDS request depends on
+    pub fn format(self, response: &[u8], level: SecurityLevel) -> SecretString {
+        match self {
+            OutputFormat::Hex => bytes_to_secret_hex(response),
+            OutputFormat::Upper => join_bytes(response, "", ""),
+            OutputFormat::Spaced => join_bytes(response, " ", ""),
+            OutputFormat::CList => join_bytes(response, ", ", "0x"),
+            OutputFormat::Uds => join_bytes(&SecretBytes(uds::send_key(level, response)), " ", ""),
+            OutputFormat::Base64 => {
+                let mut buffer = SecretBytes::new(vec![0; Base64::encoded_len(response)]);
+                SecretString::from(Base64::encode(response, &mut buffer).expect("buffer of the encoded length"))
+            }
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Box<dyn Error>;
+
+    /// Parses the name of a format, e.g. `spaced` or `C-List`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| s.trim().eq_ignore_ascii_case(format.name()))
+            .ok_or_else(|| format!("Unknown output format: {}", s).into())
+    }
+}
+
+/// Writes the bytes as uppercase hex, each with the prefix, joined by the separator
+fn join_bytes(bytes: &[u8], separator: &str, prefix: &str) -> SecretString {
+    // Reserved up front so that no partial copy is left behind by reallocating
+    let mut text = SecretString(String::with_capacity(bytes.len() * (prefix.len() + 2 + separator.len())));
+    for (index, byte) in bytes.iter().enumerate() {
+        if index > 0 {
+            text.0.push_str(separator);
+        }
+        text.0.push_str(prefix);
+        text.0.push(HEX_UPPER[usize::from(byte >> 4)] as char);
+        text.0.push(HEX_UPPER[usize::from(byte & 0x0F)] as char);
+    }
+    text
+}
+
+const HEX_UPPER: &[u8; 16] = b"0123456789ABCDEF";
+
+pub fn xor_bytes(a: &[u8], b: &[u8]) -> Result<SecretBytes, Box<dyn Error>> {
     if a.len() != b.len() {
         let s: String = format!("Length mismatch, a: {}, b: {}", a.len(), b.len());
         return Err(s.into());
     }
 
-    Ok(a.iter()
+    Ok(SecretBytes(a.iter()
      .zip(b.iter())
      .map(|(a, b)| a ^ b)
-     .collect())
-}
\ No newline at end of file
+     .collect()))
+}
@@ -0,0 +1,82 @@
+// Seed-to-key algorithms and their selection from mask table entries
+
+mod common;
+
+use seed2cmac::crypto::{algorithm, algorithms, AesEcb, HmacSha256, SeedKeyAlgorithm, ShiftXor32};
+use seed2cmac::{MaskTable, SecurityLevel, SeedKeyCalculator};
+
+use common::hex;
+
+#[test]
+fn every_algorithm_can_be_named_in_mask_files() {
+    let level = SecurityLevel::new(1).unwrap();
+    for implemented in algorithms() {
+        let name = implemented.name();
+        assert_eq!(algorithm(name).map(|a| a.name()), Some(name));
+
+        let seed_len = [16, 4].into_iter().find(|len| implemented.output_len(*len).is_ok()).unwrap();
+        let table = MaskTable::parse(&format!("X 1 = {} seed={} algo={}", "00".repeat(seed_len), seed_len, name)).unwrap();
+        let entry = table.get(&table.ecu("X").unwrap(), level).unwrap();
+        assert_eq!(entry.algorithm, name);
+        assert_eq!(entry.response_len, implemented.output_len(seed_len).unwrap());
+    }
+}
+
+#[test]
+fn hmac_sha256_rfc4231_case2() {
+    let response = HmacSha256.calculate(b"what do ya want for nothing?", b"Jefe", None).unwrap();
+    assert_eq!(response, hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"));
+}
+
+#[test]
+fn aes_ecb_fips197() {
+    let key = hex("000102030405060708090a0b0c0d0e0f");
+    let response = AesEcb.calculate(&hex("00112233445566778899aabbccddeeff"), &key, None).unwrap();
+    assert_eq!(response, hex("69c4e0d86a7b0430d8cdb78070b4c55a"));
+    assert!(AesEcb.calculate(&[0; 8], &key, None).is_err());
+}
+
+#[test]
+fn shift_xor32_key_length() {
+    assert!(ShiftXor32.check_key(&[0; 4], None).is_ok());
+    assert!(ShiftXor32.check_key(&[0; 16], None).is_err());
+}
+
+#[test]
+fn mixed_fleet_table() {
+    let table = MaskTable::parse(
+        "BMS 1 = 00000000000000000000000000000000\n\
+         OLD 1 = 0f0f0f0f seed=4 algo=shift-xor32\n\
+         GW 1 = 00000000000000000000000000000000 algo=hmac-sha256 response=8\n",
+    )
+    .unwrap();
+    let level = SecurityLevel::new(1).unwrap();
+
+    let old = SeedKeyCalculator::from_table(&table, &table.ecu("OLD").unwrap(), level).unwrap();
+    assert_eq!(old.algorithm().name(), "shift-xor32");
+    assert_eq!(old.calculate_hex("12345678", "A5A5A5A5").unwrap(), "3afb36b0");
+    assert!(old.calculate_hex("12345678", "00112233445566778899AABBCCDDEEFF").is_err());
+
+    let gw = SeedKeyCalculator::from_table(&table, &table.ecu("GW").unwrap(), level).unwrap();
+    assert_eq!(gw.calculate(&[0; 16], b"Jefe").unwrap().len(), 8);
+
+    let bms = SeedKeyCalculator::from_table(&table, &table.ecu("BMS").unwrap(), level).unwrap();
+    assert_eq!(bms.algorithm().name(), "cmac");
+    assert_eq!(bms.key_lengths(), vec![16, 24, 32]);
+}
+
+#[test]
+fn invalid_algorithm_attributes() {
+    assert!(MaskTable::parse("X 1 = 00 algo=rot13").is_err());
+    assert!(MaskTable::parse("X 1 = 0011 seed=2 algo=shift-xor32").is_err());
+    assert!(MaskTable::parse("X 1 = 00112233 seed=4 algo=shift-xor32 response=8").is_err());
+    assert!(MaskTable::parse("X 1 = 00112233 seed=4 algo=hmac-sha256 cipher=aes128").is_err());
+    assert!(MaskTable::parse("X 1 = 00112233 seed=4 algo=hmac-sha256 response=32").is_ok());
+
+    let err = MaskTable::parse("# legacy\nX 1 = 00112233 seed=4 algo=rot13\nX 3 = 00112233 seed=4 algo=cmac cipher=aes128 response=17").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "line 2: unknown algorithm `rot13`, expected one of cmac, hmac-sha256, aes-ecb, shift-xor32\n\
+         line 3: response length must be 1 to 16 bytes, found 17"
+    );
+}
@@ -0,0 +1,146 @@
+// Hash-chained audit log of key calculations
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+use seed2cmac::audit::{verify_chain, AuditEvent, AuditLog};
+use seed2cmac::batch;
+use seed2cmac::service::KeyService;
+use seed2cmac::util::OutputFormat;
+use seed2cmac::{MaskTable, SecurityLevel};
+
+use common::{KEY, SEED};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("seed2cmac-audit-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn event(seed: &[u8]) -> AuditEvent {
+    AuditEvent::new("headless", "BMS", SecurityLevel::new(1).unwrap(), seed, &[0xAA; 16])
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+#[test]
+fn entries_are_chained() {
+    let dir = temp_dir("chain");
+    let log = AuditLog::new(dir.join("audit.jsonl"));
+    log.record(&event(b"first")).unwrap();
+    log.record_all(&[event(b"second"), event(b"third")]).unwrap();
+    assert_eq!(log.verify().unwrap(), 3);
+
+    let entries = log.entries().unwrap();
+    assert_eq!(entries.iter().map(|e| e.record.seq).collect::<Vec<_>>(), [1, 2, 3]);
+    assert_eq!(entries[1].record.prev, entries[0].hash);
+    assert_eq!(entries[0].record.level, "0x01");
+    assert_eq!(entries[0].record.timestamp.len(), "2024-05-01T08:30:00Z".len());
+    assert_eq!(entries[0].record.result_sha256, hex::encode(sha256(&[0xAA; 16])));
+
+    // The log never contains the seed or the response itself
+    let contents = fs::read_to_string(log.path()).unwrap();
+    assert!(!contents.contains(&"aa".repeat(16)));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn concurrent_writers_keep_the_chain() {
+    let dir = temp_dir("concurrent");
+    let path = dir.join("audit.jsonl");
+    let writers: Vec<_> = (0..4)
+        .map(|writer| {
+            let log = AuditLog::new(&path);
+            std::thread::spawn(move || {
+                for index in 0..25 {
+                    log.record(&event(format!("{writer}-{index}").as_bytes())).unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    assert_eq!(AuditLog::new(&path).verify().unwrap(), 100);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tampering_is_detected() {
+    let dir = temp_dir("tamper");
+    let log = AuditLog::new(dir.join("audit.jsonl"));
+    log.record_all(&[event(b"first"), event(b"second"), event(b"third")]).unwrap();
+    let contents = fs::read_to_string(log.path()).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+
+    let edited = contents.replacen("\"BMS\"", "\"VCU\"", 1);
+    assert!(verify_chain(&edited).unwrap_err().to_string().contains("line 1"));
+
+    let removed = [lines[0], lines[2]].join("\n");
+    assert!(verify_chain(&removed).is_err());
+
+    let reordered = [lines[1], lines[0], lines[2]].join("\n");
+    assert!(verify_chain(&reordered).is_err());
+    assert_eq!(verify_chain("").unwrap(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn batch_and_service_are_recorded() {
+    let dir = temp_dir("batch");
+    let log = AuditLog::new(dir.join("audit.jsonl"));
+    let table = MaskTable::parse("A 1 = 0102030405060708090a0b0c0d0e0f10").unwrap();
+
+    let input = dir.join("eol.csv");
+    fs::write(&input, format!("ecu,level,seed,key\nA,1,{SEED},\nA,1,00,\n")).unwrap();
+    let summary = batch::run(&table, &input, &batch::output_path(&input), Some(KEY), None, Some(&log), OutputFormat::Hex).unwrap();
+    assert_eq!(summary.failed, 1);
+
+    let service = KeyService::new(table, None).with_audit_log(log.clone());
+    let body = format!(r#"{{"ecu": "A", "level": 1, "seed": "{SEED}", "key": "{KEY}"}}"#);
+    assert_eq!(service.handle("POST", "/v1/key", None, body.as_bytes()).status, 200);
+
+    let entries = log.entries().unwrap();
+    assert_eq!(entries.iter().map(|e| e.record.source.as_str()).collect::<Vec<_>>(), ["batch", "service"]);
+    assert_eq!(entries[0].record.seed_sha256, entries[1].record.seed_sha256);
+    assert_eq!(log.verify().unwrap(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn user_is_the_account_of_the_process() {
+    let Ok(output) = std::process::Command::new("id").arg("-un").output() else { return };
+    let account = String::from_utf8(output.stdout).unwrap();
+
+    let dir = temp_dir("user");
+    let log = AuditLog::new(dir.join("audit.jsonl"));
+    log.record(&event(b"seed")).unwrap();
+    assert_eq!(log.entries().unwrap()[0].record.user, account.trim());
+}
+
+#[test]
+fn verification_on_the_command_line_is_recorded() {
+    let dir = temp_dir("verify");
+    let path = dir.join("audit.jsonl");
+    let response = "0000000000000000000000000000000f";
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_seed2cmac"))
+        .args(["--ecu", "BMS", "--level", "1", "--seed", SEED, "--key", KEY, "--response", response, "--audit-log"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().next(), Some("mismatch"));
+
+    let log = AuditLog::new(path);
+    let entries = log.entries().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].record.source, "headless-verify");
+    assert_eq!(entries[0].record.result_sha256, hex::encode(sha256(&hex::decode(response).unwrap())));
+}
@@ -0,0 +1,116 @@
+// Batch key calculation from CSV and JSON Lines files
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use seed2cmac::batch::{self, BatchFormat, BatchSummary};
+use seed2cmac::util::{hex_string_to_bytes, OutputFormat};
+use seed2cmac::{EcuType, MaskTable, SecurityLevel, SeedKeyCalculator};
+
+use common::{hex, KEY, SEED, TABLE};
+
+fn expected(level: u8, seed: &str) -> String {
+    let calculator = SeedKeyCalculator::from_table(&MaskTable::parse(TABLE).unwrap(), &EcuType::new("A"), SecurityLevel::new(level).unwrap()).unwrap();
+    hex::encode(calculator.calculate(&hex(seed), &hex(KEY)).unwrap())
+}
+
+#[test]
+fn csv_rows_fail_individually() {
+    let input = format!(
+        "ecu,level,seed,key\n\
+         A,1,{SEED},\n\
+         A,0x11,a1b2c3d4,{KEY}\n\
+         B,1,{SEED},\n\
+         A,1,0011,\n\
+         A\n\
+         A,2,{SEED},\n"
+    );
+    let table = MaskTable::parse(TABLE).unwrap();
+    let rows = batch::read_rows(input.as_bytes(), BatchFormat::Csv).unwrap();
+    let results = batch::process(&table, rows, Some(KEY), None);
+
+    assert_eq!(results.len(), 6);
+    assert_eq!(results[0].response, expected(1, SEED));
+    assert_eq!(results[1].response, expected(0x11, "a1b2c3d4"));
+    assert!(results[2].error.contains("Unknown ECU type"));
+    assert!(results[3].error.contains("Invalid seed"));
+    assert!(!results[4].error.is_empty());
+    assert!(!results[5].error.is_empty());
+    assert_eq!(results.iter().map(|r| r.line).collect::<Vec<_>>(), [2, 3, 4, 5, 6, 7]);
+
+    // Rows without a key fail without a default key
+    let rows = batch::read_rows(input.as_bytes(), BatchFormat::Csv).unwrap();
+    let results = batch::process(&table, rows, None, None);
+    assert!(results[0].error.contains("No key"));
+    assert!(results[1].is_ok());
+}
+
+#[test]
+fn responses_in_output_format() {
+    let input = format!("ecu,level,seed\nA,0x11,a1b2c3d4\nB,1,{SEED}\n");
+    let table = MaskTable::parse(TABLE).unwrap();
+    let mut results = batch::process(&table, batch::read_rows(input.as_bytes(), BatchFormat::Csv).unwrap(), Some(KEY), None);
+    batch::format_responses(&mut results, OutputFormat::Uds);
+
+    let response = hex_string_to_bytes(&expected(0x11, "a1b2c3d4")).unwrap();
+    assert_eq!(results[0].response, format!("27 12 {:02X} {:02X} {:02X} {:02X}", response[0], response[1], response[2], response[3]));
+    assert!(results[1].response.is_empty());
+}
+
+#[test]
+fn json_lines_file() {
+    let dir = std::env::temp_dir().join(format!("seed2cmac-batch-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("eol.jsonl");
+    fs::write(
+        &input,
+        format!(
+            "{{\"ecu\": \"A\", \"level\": 1, \"seed\": \"{SEED}\", \"key\": \"{KEY}\"}}\n\
+             \n\
+             not json\n\
+             {{\"ecu\": \"A\", \"level\": \"0x11\", \"seed\": \"a1b2c3d4\"}}\n"
+        ),
+    )
+    .unwrap();
+
+    let output = batch::output_path(&input);
+    assert_eq!(output, dir.join("eol.keys.jsonl"));
+    let table = MaskTable::parse(TABLE).unwrap();
+    assert_eq!(batch::run(&table, &input, &output, Some(KEY), None, None, OutputFormat::Hex).unwrap(), BatchSummary { rows: 3, failed: 1 });
+
+    let lines: Vec<serde_json::Value> =
+        fs::read_to_string(&output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines[0]["response"], expected(1, SEED));
+    assert_eq!(lines[1]["line"], 3);
+    assert_ne!(lines[1]["error"], "");
+    assert_eq!(lines[2]["level"], "0x11");
+    assert_eq!(lines[2]["response"], expected(0x11, "a1b2c3d4"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn format_and_output_path() {
+    assert_eq!(BatchFormat::from_path(Path::new("eol.csv")), Some(BatchFormat::Csv));
+    assert_eq!(BatchFormat::from_path(Path::new("eol.NDJSON")), Some(BatchFormat::JsonLines));
+    assert_eq!(BatchFormat::from_path(Path::new("eol.json")), None);
+    assert_eq!(BatchFormat::from_path(Path::new("eol.txt")), None);
+    assert_eq!(BatchFormat::from_path(Path::new("eol")), None);
+    assert_eq!(batch::output_path(Path::new("/tmp/eol.csv")), Path::new("/tmp/eol.keys.csv"));
+}
+
+#[test]
+fn json_arrays_and_other_files_are_rejected() {
+    let dir = std::env::temp_dir().join(format!("seed2cmac-batch-json-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("eol.json");
+    fs::write(&input, format!("[{{\"ecu\": \"A\", \"level\": 1, \"seed\": \"{SEED}\"}}]\n")).unwrap();
+
+    let output = dir.join("eol.keys.json");
+    let table = MaskTable::parse(TABLE).unwrap();
+    let err = batch::run(&table, &input, &output, Some(KEY), None, None, OutputFormat::Hex).unwrap_err();
+    assert!(err.to_string().contains("unsupported batch file"));
+    assert!(!output.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,80 @@
+// NIST SP 800-38B, Appendix D and RFC 4493, Section 4: CMAC-AES examples
+
+use seed2cmac::crypto::{calculate_cmac, calculate_cmac_with, select_cipher, CmacCipher};
+use seed2cmac::util::hex_string_to_bytes;
+
+const MESSAGE: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+
+/// Message lengths of the four examples per key size
+const LENGTHS: [usize; 4] = [0, 16, 40, 64];
+
+fn check(cipher: CmacCipher, key: &str, expected: [&str; 4]) {
+    let key = hex_string_to_bytes(key).unwrap();
+    let message = hex_string_to_bytes(MESSAGE).unwrap();
+
+    for (len, expected) in LENGTHS.into_iter().zip(expected) {
+        let expected = hex_string_to_bytes(expected).unwrap();
+        assert_eq!(calculate_cmac_with(cipher, &key, &message[..len]).unwrap(), expected, "{} Mlen={}", cipher, len);
+        assert_eq!(calculate_cmac(&key, &message[..len]).unwrap(), expected, "{} Mlen={}", cipher, len);
+    }
+}
+
+#[test]
+fn aes128() {
+    check(
+        CmacCipher::Aes128,
+        "2b7e151628aed2a6abf7158809cf4f3c",
+        [
+            "bb1d6929e95937287fa37d129b756746",
+            "070a16b46b4d4144f79bdd9dd04a287c",
+            "dfa66747de9ae63030ca32611497c827",
+            "51f0bebf7e3b9d92fc49741779363cfe",
+        ],
+    );
+}
+
+#[test]
+fn aes192() {
+    check(
+        CmacCipher::Aes192,
+        "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b",
+        [
+            "d17ddf46adaacde531cac483de7a9367",
+            "9e99a7bf31e710900662f65e617c5184",
+            "8a1de5be2eb31aad089a82e6ee908b0e",
+            "a1d5df0eed790f794d77589659f39a11",
+        ],
+    );
+}
+
+#[test]
+fn aes256() {
+    check(
+        CmacCipher::Aes256,
+        "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4",
+        [
+            "028962f61b7bf89efc6b551f4667d983",
+            "28a7023f452e8f82bd4bf28d8c37c35c",
+            "aaf3d8f1de5640c232f5b169b9c911e6",
+            "e1992190549f6ed5696a2c056c315410",
+        ],
+    );
+}
+
+#[test]
+fn key_length_must_match_cipher() {
+    let key = [0u8; 16];
+
+    let err = calculate_cmac_with(CmacCipher::Aes256, &key, b"").unwrap_err();
+    assert_eq!(err.to_string(), "AES-256 requires a 32 byte key, got 16 bytes");
+
+    assert!(calculate_cmac(&key[..15], b"").is_err());
+    assert_eq!(select_cipher(None, 24).unwrap(), CmacCipher::Aes192);
+}
+
+#[test]
+fn cipher_names() {
+    assert_eq!("aes128".parse::<CmacCipher>().unwrap(), CmacCipher::Aes128);
+    assert_eq!("AES-192".parse::<CmacCipher>().unwrap(), CmacCipher::Aes192);
+    assert!("aes512".parse::<CmacCipher>().is_err());
+}
@@ -0,0 +1,20 @@
+// Fixtures shared by the integration tests
+
+#![allow(dead_code)]
+
+use seed2cmac::util::{hex_string_to_bytes, SecretBytes};
+
+/// AES-128 key of the RFC 4493 test vectors
+pub const KEY: &str = "2b7e151628aed2a6abf7158809cf4f3c";
+
+pub const SEED: &str = "00112233445566778899aabbccddeeff";
+
+/// ECU `A` with a full CMAC at level 1 and a 4-byte seed and response at level 0x11
+pub const TABLE: &str = "\
+A 1 = 0102030405060708090a0b0c0d0e0f10
+A 0x11 = 01020304 seed=4 response=4
+";
+
+pub fn hex(s: &str) -> SecretBytes {
+    hex_string_to_bytes(s).unwrap()
+}
@@ -0,0 +1,76 @@
+// DoIP framing and security access through the loopback gateway
+
+mod common;
+
+use std::net::{TcpListener, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use seed2cmac::client;
+use seed2cmac::doip::{self, DoIpClient, DoIpGateway, VehicleIdentification};
+use seed2cmac::simulator::{SimulatedEcu, SimulatorConfig};
+use seed2cmac::uds::NegativeResponse;
+use seed2cmac::util::hex_string_to_bytes;
+use seed2cmac::{EcuType, MaskTable, SecurityLevel, SeedKeyCalculator};
+
+use common::KEY;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn identification() -> VehicleIdentification {
+    VehicleIdentification { vin: String::from("WDB12345678901234"), logical_address: 0x1001, eid: [2, 0, 0, 0, 0, 1], gid: [0; 6] }
+}
+
+#[test]
+fn encode_and_decode_messages() {
+    let message = doip::encode(0x8001, &[0x0E, 0x00, 0x10, 0x01, 0x27, 0x01]);
+    assert_eq!(message, [0x02, 0xFD, 0x80, 0x01, 0, 0, 0, 6, 0x0E, 0x00, 0x10, 0x01, 0x27, 0x01]);
+    assert_eq!(doip::decode(&message).unwrap(), (0x8001, &message[8..]));
+
+    assert!(doip::decode(&message[..10]).is_err());
+    assert!(doip::decode(&[0x02, 0xFC, 0, 1, 0, 0, 0, 0]).is_err());
+    assert!(doip::decode(&[0x02]).is_err());
+
+    assert_eq!(doip::parse_logical_address("0x0E00"), Ok(0x0E00));
+    assert_eq!(doip::parse_logical_address("4097"), Ok(0x1001));
+    assert!(doip::parse_logical_address("0x10000").is_err());
+    assert_eq!(doip::with_default_port("127.0.0.1"), "127.0.0.1:13400");
+}
+
+#[test]
+fn vehicle_identification_over_udp() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap().to_string();
+    thread::spawn(move || doip::serve_identification(&socket, &identification()));
+
+    assert_eq!(doip::identify(&addr, TIMEOUT).unwrap(), identification());
+}
+
+#[test]
+fn unlock_through_gateway() {
+    let table = MaskTable::parse("XCU 1 = 0102030405060708090a0b0c0d0e0f10").unwrap();
+    let ecu = EcuType::new("XCU");
+    let key = hex_string_to_bytes(KEY).unwrap();
+    let mut gateway = DoIpGateway::new(identification());
+    gateway.add_ecu(0x1001, SimulatedEcu::new(&table, &ecu, key.clone(), SimulatorConfig::default()).unwrap());
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let _ = gateway.serve_tcp(&listener, &mut |_, _, _| {});
+    });
+
+    let calculator = SeedKeyCalculator::from_table(&table, &ecu, SecurityLevel::new(1).unwrap()).unwrap();
+    let mut transport = DoIpClient::connect(&addr, 0x0E00, 0x1001, TIMEOUT).unwrap();
+    assert_eq!(transport.activate_routing().unwrap(), 0x1001);
+    let report = client::unlock(&mut transport, &calculator, &key).unwrap();
+    assert!(report.key.is_some());
+
+    let err = client::unlock(&mut transport, &calculator, &[0; 16]).unwrap_err();
+    assert_eq!(err.downcast_ref::<NegativeResponse>().unwrap().nrc, 0x35);
+    drop(transport);
+
+    let mut transport = DoIpClient::connect(&addr, 0x0E00, 0x1234, TIMEOUT).unwrap();
+    let err = client::unlock(&mut transport, &calculator, &key).unwrap_err();
+    assert!(err.to_string().contains("unknown target address"));
+}
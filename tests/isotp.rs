@@ -0,0 +1,201 @@
+// ISO-TP transport and security access over an in-memory CAN bus
+
+mod common;
+
+use std::io;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use seed2cmac::can::{CanFrame, CanInterface};
+use seed2cmac::client::{self, UnlockReport};
+use seed2cmac::isotp::{IsoTp, IsoTpConfig};
+use seed2cmac::uds::NegativeResponse;
+use seed2cmac::util::hex_string_to_bytes;
+use seed2cmac::{EcuType, SecurityLevel, SeedKeyCalculator};
+
+use common::{hex, KEY, SEED};
+
+/// One end of a point-to-point CAN bus
+struct Bus {
+    tx: Sender<CanFrame>,
+    rx: Receiver<CanFrame>,
+}
+
+impl CanInterface for Bus {
+    fn send(&mut self, frame: &CanFrame) -> io::Result<()> {
+        assert!(frame.data.len() <= 8);
+        self.tx.send(frame.clone()).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    fn recv(&mut self, timeout: Duration) -> io::Result<Option<CanFrame>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+}
+
+/// Returns the tester and ECU ends of an ISO-TP connection on 0x7E0/0x7E8
+fn connection() -> (IsoTp<Bus>, IsoTp<Bus>) {
+    let (tester_tx, ecu_rx) = channel();
+    let (ecu_tx, tester_rx) = channel();
+    let tester = IsoTp::new(Bus { tx: tester_tx, rx: tester_rx }, IsoTpConfig::new(0x7E0, 0x7E8));
+    let ecu = IsoTp::new(Bus { tx: ecu_tx, rx: ecu_rx }, IsoTpConfig::new(0x7E8, 0x7E0));
+    (tester, ecu)
+}
+
+#[test]
+fn single_and_segmented_messages() {
+    let (mut tester, mut ecu) = connection();
+    let long: Vec<u8> = (0..=255u8).cycle().take(300).collect();
+
+    let echo = thread::spawn(move || {
+        for _ in 0..2 {
+            let message = ecu.receive().unwrap();
+            ecu.send(&message).unwrap();
+        }
+    });
+
+    tester.send(&[0x10, 0x03]).unwrap();
+    assert_eq!(tester.receive().unwrap(), [0x10, 0x03]);
+    tester.send(&long).unwrap();
+    assert_eq!(tester.receive().unwrap(), long);
+    echo.join().unwrap();
+}
+
+#[test]
+fn timeout_without_flow_control() {
+    let (tx, _peer_rx) = channel();
+    let (_peer_tx, rx) = channel();
+    let mut config = IsoTpConfig::new(0x7E0, 0x7E8);
+    config.timeout = Duration::from_millis(20);
+    let mut tester = IsoTp::new(Bus { tx, rx }, config);
+
+    assert!(tester.send(&[0; 20]).unwrap_err().to_string().contains("Timeout"));
+    assert!(tester.receive().unwrap_err().to_string().contains("Timeout"));
+}
+
+#[test]
+fn flow_control_wait_is_limited() {
+    let (tx, peer_rx) = channel();
+    let (peer_tx, rx) = channel();
+    let mut config = IsoTpConfig::new(0x7E0, 0x7E8);
+    config.max_wait_frames = 2;
+    let mut tester = IsoTp::new(Bus { tx, rx }, config);
+
+    let ecu = thread::spawn(move || {
+        let first_frame = peer_rx.recv().unwrap();
+        assert_eq!(first_frame.data[0] & 0xF0, 0x10);
+        for _ in 0..3 {
+            peer_tx.send(CanFrame { id: 0x7E8, data: vec![0x31, 0, 0] }).unwrap();
+        }
+        // Keeps the bus open until the tester gave up
+        let _ = peer_rx.recv();
+    });
+
+    let err = tester.send(&[0; 20]).unwrap_err();
+    assert_eq!(err.to_string(), "ISO-TP receiver sent more than 2 flow control WAIT frames");
+    drop(tester);
+    ecu.join().unwrap();
+}
+
+#[test]
+fn response_pending_extends_the_response_time() {
+    let (mut tester, mut ecu) = connection();
+    let ecu = thread::spawn(move || {
+        let request = ecu.receive().unwrap();
+        ecu.send(&[0x7F, request[0], 0x78]).unwrap();
+        // Longer than both P2 and the ISO-TP timeout of one second
+        thread::sleep(Duration::from_millis(1500));
+        ecu.send(&[0x67, 0x01, 0xA1, 0xB2]).unwrap();
+        // No response at all to the next request, keeping the bus open meanwhile
+        ecu.receive().unwrap();
+        thread::sleep(Duration::from_secs(1));
+    });
+
+    assert_eq!(client::request(&mut tester, &[0x27, 0x01]).unwrap(), [0x67, 0x01, 0xA1, 0xB2]);
+
+    let start = Instant::now();
+    let err = client::request(&mut tester, &[0x3E, 0x00]).unwrap_err();
+    assert_eq!(err.downcast_ref::<io::Error>().unwrap().kind(), io::ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    ecu.join().unwrap();
+}
+
+/// Answers one security access with a fixed seed, accepting only the expected key
+fn run_ecu(mut ecu: IsoTp<Bus>, seed: Vec<u8>, expected_key: Vec<u8>) {
+    loop {
+        let Ok(request) = ecu.receive() else { return };
+        let response = match request.as_slice() {
+            [0x10, 0x03] => vec![0x50, 0x03, 0x00, 0x32, 0x01, 0xF4],
+            [0x27, 0x01] => {
+                // Report response pending before the seed
+                ecu.send(&[0x7F, 0x27, 0x78]).unwrap();
+                [&[0x67, 0x01][..], &seed].concat()
+            }
+            [0x27, 0x02, key @ ..] if key == expected_key.as_slice() => vec![0x67, 0x02],
+            [0x27, 0x02, ..] => vec![0x7F, 0x27, 0x35],
+            _ => vec![0x7F, request[0], 0x11],
+        };
+        ecu.send(&response).unwrap();
+    }
+}
+
+fn unlock_with(key: &str) -> Result<UnlockReport, Box<dyn std::error::Error>> {
+    let calculator = SeedKeyCalculator::new(&EcuType::new("BMS"), SecurityLevel::new(1).unwrap()).unwrap();
+    let seed = hex(SEED).to_vec();
+    let expected_key = calculator.calculate(&seed, &hex(KEY)).unwrap().to_vec();
+
+    let (mut tester, ecu) = connection();
+    let ecu = thread::spawn(move || run_ecu(ecu, seed, expected_key));
+    let result = client::unlock(&mut tester, &calculator, &hex_string_to_bytes(key).unwrap());
+    drop(tester);
+    ecu.join().unwrap();
+    result
+}
+
+#[test]
+fn unlock_ecu() {
+    let report = unlock_with(KEY).unwrap();
+    assert_eq!(hex_string_to_bytes("00112233445566778899aabbccddeeff").unwrap(), report.seed);
+    assert_eq!(report.key.unwrap().len(), 16);
+}
+
+#[test]
+fn unlock_with_wrong_key_reports_invalid_key() {
+    let err = unlock_with("000102030405060708090a0b0c0d0e0f").unwrap_err();
+    assert_eq!(err.downcast_ref::<NegativeResponse>().unwrap().nrc, 0x35);
+}
+
+/// Runs `--simulate --can vcan0` and unlocks the simulated ECU on vcan0, see
+/// the README for creating the interface
+#[cfg(target_os = "linux")]
+#[test]
+#[ignore = "needs the virtual CAN interface vcan0"]
+fn unlock_simulated_ecu_on_vcan0() {
+    use std::process::{Command, Stdio};
+
+    let exe = env!("CARGO_BIN_EXE_seed2cmac");
+    let mut simulator = Command::new(exe)
+        .args(["--simulate", "--ecu", "BMS", "--key", KEY, "--can", "vcan0"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    // Give the simulator time to open the interface
+    thread::sleep(Duration::from_millis(500));
+
+    let audit_log = std::env::temp_dir().join(format!("seed2cmac-vcan0-{}.jsonl", std::process::id()));
+    let output = Command::new(exe)
+        .args(["--ecu", "BMS", "--level", "1", "--key", KEY, "--can", "vcan0", "--audit-log"])
+        .arg(&audit_log)
+        .output()
+        .unwrap();
+    simulator.kill().unwrap();
+    simulator.wait().unwrap();
+    let _ = std::fs::remove_file(&audit_log);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("unlocked"));
+}
@@ -0,0 +1,109 @@
+// Encrypted key store and `@alias` key references
+
+mod common;
+
+use seed2cmac::batch::{self, BatchRow};
+use seed2cmac::keystore::{resolve_key, KeyStore};
+use seed2cmac::service::KeyService;
+use seed2cmac::util::{hex_string_to_bytes, SecretBytes, SecretString};
+use seed2cmac::MaskTable;
+
+use common::KEY;
+
+fn store() -> KeyStore {
+    let mut store = KeyStore::new();
+    store.insert("BMS/P1/prod", hex_string_to_bytes(KEY).unwrap()).unwrap();
+    store.insert("dev", vec![0x11; 32].into()).unwrap();
+    store
+}
+
+#[test]
+fn encrypt_and_decrypt() {
+    let contents = store().encrypt("correct horse").unwrap();
+    assert!(!contents.contains(KEY));
+    assert!(!contents.contains("BMS/P1/prod"));
+
+    let decrypted = KeyStore::decrypt(&contents, "correct horse").unwrap();
+    assert_eq!(decrypted.aliases(), ["BMS/P1/prod", "dev"]);
+    assert_eq!(hex_string_to_bytes(KEY).unwrap(), decrypted.get("BMS/P1/prod").unwrap());
+
+    let err = KeyStore::decrypt(&contents, "wrong").unwrap_err();
+    assert!(err.to_string().contains("Wrong passphrase"));
+}
+
+#[test]
+fn weak_or_excessive_kdf_parameters_are_rejected() {
+    let contents = store().encrypt("correct horse").unwrap();
+    let mut file: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    for (field, value) in [("m_cost", 8), ("m_cost", 64 * 1024 * 1024), ("t_cost", 1), ("t_cost", 1_000_000), ("p_cost", 0), ("p_cost", 255)] {
+        let original = file[field].clone();
+        file[field] = value.into();
+        let err = KeyStore::decrypt(&file.to_string(), "correct horse").unwrap_err();
+        assert!(err.to_string().contains(field), "{}", err);
+        file[field] = original;
+    }
+
+    file["salt"] = "00".into();
+    assert!(KeyStore::decrypt(&file.to_string(), "correct horse").unwrap_err().to_string().contains("salt"));
+}
+
+#[test]
+fn aliases_and_references() {
+    let mut store = store();
+    assert!(store.insert("bad alias", vec![1].into()).is_err());
+    assert!(store.insert("empty", SecretBytes::default()).is_err());
+    assert!(store.remove("dev"));
+    assert!(!store.remove("dev"));
+
+    // Only aliases are shown
+    assert!(!format!("{:?}", store).contains(KEY));
+
+    assert_eq!(resolve_key("@BMS/P1/prod", Some(&store)).unwrap(), hex_string_to_bytes(KEY).unwrap());
+    assert_eq!(resolve_key(KEY, None).unwrap(), hex_string_to_bytes(KEY).unwrap());
+    assert!(resolve_key("@dev", Some(&store)).unwrap_err().to_string().contains("Unknown key alias"));
+    assert!(resolve_key("@BMS/P1/prod", None).unwrap_err().to_string().contains("locked"));
+}
+
+#[test]
+fn alias_in_batch_row_and_service() {
+    let table = MaskTable::parse("A 1 = 0102030405060708090a0b0c0d0e0f10").unwrap();
+    let row = BatchRow {
+        line: 2,
+        ecu: String::from("A"),
+        level: String::from("1"),
+        seed: String::from("00112233445566778899aabbccddeeff"),
+        key: Some(SecretString::from("@BMS/P1/prod")),
+    };
+    let expected = batch::calculate_row(&table, &BatchRow { key: Some(SecretString::from(KEY)), ..row.clone() }, None, None).unwrap();
+    assert_eq!(batch::calculate_row(&table, &row, None, Some(&store())).unwrap(), expected);
+    assert!(batch::calculate_row(&table, &row, None, None).is_err());
+
+    let service = KeyService::new(table, None).with_key_store(store());
+    let body = r#"{"ecu": "A", "level": 1, "seed": "00112233445566778899aabbccddeeff", "key": "@BMS/P1/prod"}"#;
+    let response = service.handle("POST", "/v1/key", None, body.as_bytes());
+    assert_eq!(response.status, 200);
+    assert!(response.body.contains(&hex::encode(expected)));
+}
+
+#[test]
+fn saved_store_replaces_the_file_and_decrypts() {
+    let dir = std::env::temp_dir().join(format!("seed2cmac-keystore-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("keys.json");
+
+    store().save(&path, "correct horse").unwrap();
+    let mut updated = KeyStore::open(&path, "correct horse").unwrap();
+    updated.remove("dev");
+    updated.save(&path, "correct horse").unwrap();
+
+    assert_eq!(KeyStore::open(&path, "correct horse").unwrap().aliases(), ["BMS/P1/prod"]);
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(files, ["keys.json"]);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
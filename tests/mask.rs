@@ -0,0 +1,69 @@
+// Loading mask tables from files on top of the built-in table
+
+use std::fs;
+use std::path::PathBuf;
+
+use seed2cmac::{EcuType, MaskTable, SecurityLevel};
+
+const MASK: &str = "ffffffffffffffffffffffffffffffff";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("seed2cmac-mask-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn level(level: u8) -> SecurityLevel {
+    SecurityLevel::new(level).unwrap()
+}
+
+#[test]
+fn user_files_override_builtin_entries() {
+    let dir = temp_dir("override");
+    let first = dir.join("first.txt");
+    let second = dir.join("second.txt");
+    fs::write(&first, format!("BMS 1 = {MASK}\nNEW 1 = {MASK}\n")).unwrap();
+    fs::write(&second, "NEW 1 = 0102030405060708090a0b0c0d0e0f10\n").unwrap();
+
+    // The built-in table and any mask files of the config directory
+    let base = MaskTable::load(&[]).unwrap();
+    let table = MaskTable::load(&[first, second]).unwrap();
+    let bms = EcuType::new("BMS");
+
+    assert_eq!(table.get(&bms, level(1)).unwrap().mask, vec![0xFF; 16]);
+    // Other levels of an overridden ECU keep their earlier masks
+    assert_eq!(table.get(&bms, level(9)), base.get(&bms, level(9)));
+    // Later files win over earlier ones
+    assert_eq!(table.get(&EcuType::new("NEW"), level(1)).unwrap().mask, (1..=16).collect::<Vec<u8>>());
+    assert_eq!(table.entries().len(), base.entries().len() + 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_file_is_reported_with_its_path() {
+    let path = temp_dir("missing").join("absent.txt");
+    let err = MaskTable::load(std::slice::from_ref(&path)).unwrap_err().to_string();
+    assert!(err.starts_with(&format!("{}: ", path.display())), "{}", err);
+}
+
+#[test]
+fn malformed_file_is_reported_with_path_and_line() {
+    let dir = temp_dir("malformed");
+    let path = dir.join("masks.txt");
+    fs::write(&path, format!("# user masks\nBMS 1 = {MASK}\nBMS 2 = {MASK}\nESP 1 = 00\n")).unwrap();
+
+    let err = MaskTable::from_file(&path).unwrap_err().to_string();
+    assert_eq!(
+        err,
+        format!(
+            "{path}:3: security level 0x02 is not a UDS requestSeed sub-function\n\
+             {path}:4: mask is 1 bytes but seed length is 16",
+            path = path.display()
+        )
+    );
+    assert_eq!(MaskTable::load(&[path]).unwrap_err().to_string(), err);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
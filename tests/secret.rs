@@ -0,0 +1,38 @@
+// Secret keys, seeds and responses are redacted in Debug output
+
+mod common;
+
+use seed2cmac::util::{hex_string_to_bytes, xor_bytes, SecretBytes, SecretString};
+use seed2cmac::{EcuType, SecurityLevel, SeedKeyCalculator};
+
+use common::KEY;
+
+#[test]
+fn debug_is_redacted() {
+    let key = hex_string_to_bytes(KEY).unwrap();
+    assert_eq!(format!("{:?}", key), "SecretBytes([REDACTED; 16])");
+    assert_eq!(format!("{:?}", SecretString::from(KEY)), "SecretString([REDACTED])");
+
+    let masked = xor_bytes(&key, &[0xFF; 16]).unwrap();
+    assert!(!format!("{:?}", masked).contains("d4"));
+}
+
+#[test]
+fn calculation_returns_secrets() {
+    let calculator = SeedKeyCalculator::new(&EcuType::new("BMS"), SecurityLevel::new(1).unwrap()).unwrap();
+    let response = calculator.calculate_hex("00112233445566778899aabbccddeeff", KEY).unwrap();
+    assert_eq!(response.len(), 32);
+    assert!(!format!("{:?}", response).contains(response.as_str()));
+
+    let bytes = calculator.calculate(&hex_string_to_bytes("00112233445566778899aabbccddeeff").unwrap(), &hex_string_to_bytes(KEY).unwrap()).unwrap();
+    assert_eq!(hex::encode(&bytes), response.as_str());
+}
+
+#[test]
+fn truncate_keeps_prefix() {
+    let mut bytes = SecretBytes::from(vec![1, 2, 3, 4]);
+    bytes.truncate(2);
+    assert_eq!(bytes, [1, 2]);
+    bytes.truncate(8);
+    assert_eq!(bytes.len(), 2);
+}
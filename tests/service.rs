@@ -0,0 +1,90 @@
+// HTTP/JSON key service requests
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use seed2cmac::service::KeyService;
+use seed2cmac::MaskTable;
+
+use common::KEY;
+
+const TABLE: &str = "\
+[Powertrain]
+A 1 = 0102030405060708090a0b0c0d0e0f10
+A 0x11 = 01020304 seed=4 response=4
+B 1 = 0102030405060708090a0b0c0d0e0f10
+";
+
+fn service(token: Option<&str>) -> KeyService {
+    KeyService::new(MaskTable::parse(TABLE).unwrap(), token.map(str::to_string))
+}
+
+fn json(body: &str) -> Value {
+    serde_json::from_str(body).unwrap()
+}
+
+#[test]
+fn calculate_key() {
+    let service = service(None);
+    let body = format!(r#"{{"ecu": "A", "level": "0x11", "seed": "a1b2c3d4", "key": "{KEY}"}}"#);
+    let response = service.handle("POST", "/v1/key", None, body.as_bytes());
+    assert_eq!(response.status, 200);
+    assert_eq!(json(&response.body)["response"].as_str().unwrap().len(), 8);
+
+    let body = format!(r#"{{"ecu": "A", "level": 1, "seed": "00", "key": "{KEY}"}}"#);
+    let response = service.handle("POST", "/v1/key", None, body.as_bytes());
+    assert_eq!(response.status, 422);
+    assert!(json(&response.body)["error"].as_str().unwrap().contains("Invalid seed"));
+
+    assert_eq!(service.handle("POST", "/v1/key", None, b"{").status, 400);
+    assert_eq!(service.handle("GET", "/v1/key", None, b"").status, 405);
+    assert_eq!(service.handle("GET", "/v2/key", None, b"").status, 404);
+}
+
+#[test]
+fn list_ecus() {
+    let response = service(None).handle("GET", "/v1/ecus", None, b"");
+    assert_eq!(response.status, 200);
+    let ecus = json(&response.body)["ecus"].clone();
+    assert_eq!(ecus[0]["ecu"], "A");
+    assert_eq!(ecus[0]["group"], "Powertrain");
+    assert_eq!(ecus[0]["levels"], serde_json::json!(["0x01", "0x11"]));
+    assert_eq!(ecus[1]["ecu"], "B");
+}
+
+#[test]
+fn bearer_token() {
+    let service = service(Some("s3cret"));
+    assert_eq!(service.handle("GET", "/v1/ecus", None, b"").status, 401);
+    assert_eq!(service.handle("GET", "/v1/ecus", Some("Bearer wrong"), b"").status, 401);
+    assert_eq!(service.handle("GET", "/v1/ecus", Some("Bearer s3cret"), b"").status, 200);
+}
+
+#[test]
+fn stalled_client_does_not_block_others() {
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    thread::spawn(move || service(None).serve(&addr.to_string(), &mut |_, _, _| {}));
+
+    // Announces a body it never sends, too large for tiny_http to read ahead
+    let mut stalled = (0..50)
+        .find_map(|_| TcpStream::connect(addr).ok().or_else(|| {
+            thread::sleep(Duration::from_millis(20));
+            None
+        }))
+        .unwrap();
+    stalled.write_all(b"POST /v1/key HTTP/1.1\r\nHost: x\r\nContent-Length: 10000\r\n\r\n{").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.write_all(b"GET /v1/ecus HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+}
@@ -0,0 +1,124 @@
+// Simulated ECU answering security access directly and over TCP
+
+mod common;
+
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use seed2cmac::client::{self, UdsTransport};
+use seed2cmac::simulator::{SimulatedEcu, SimulatorConfig};
+use seed2cmac::tcp::TcpTransport;
+use seed2cmac::uds::NegativeResponse;
+use seed2cmac::{EcuType, MaskTable, SecurityLevel, SeedKeyCalculator};
+
+use common::{hex, KEY, TABLE};
+
+fn simulator(config: SimulatorConfig) -> (SimulatedEcu, MaskTable) {
+    let table = MaskTable::parse(TABLE).unwrap();
+    let ecu = SimulatedEcu::new(&table, &EcuType::new("A"), hex(KEY), config).unwrap();
+    (ecu, table)
+}
+
+fn calculator(table: &MaskTable, level: u8) -> SeedKeyCalculator {
+    SeedKeyCalculator::from_table(table, &EcuType::new("A"), SecurityLevel::new(level).unwrap()).unwrap()
+}
+
+#[test]
+fn unlock_in_extended_session() {
+    let (mut ecu, table) = simulator(SimulatorConfig::default());
+    assert_eq!(ecu.handle(&[0x27, 0x11]), [0x7F, 0x27, 0x7F]);
+    assert_eq!(ecu.handle(&[0x10, 0x03]), [0x50, 0x03, 0x00, 0x32, 0x01, 0xF4]);
+    assert_eq!(ecu.handle(&[0x27, 0x05]), [0x7F, 0x27, 0x12]);
+    assert_eq!(ecu.handle(&[0x27, 0x12, 0, 0, 0, 0]), [0x7F, 0x27, 0x24]);
+
+    let response = ecu.handle(&[0x27, 0x11]);
+    assert_eq!(response[..2], [0x67, 0x11]);
+    assert_eq!(response.len(), 6);
+
+    let key = calculator(&table, 0x11).calculate(&response[2..], &hex(KEY)).unwrap();
+    assert_eq!(ecu.handle(&[&[0x27, 0x12][..], &key].concat()), [0x67, 0x12]);
+    assert_eq!(ecu.unlocked(), SecurityLevel::new(0x11).ok());
+
+    // An unlocked level answers with an all-zero seed until the session changes
+    assert_eq!(ecu.handle(&[0x27, 0x11]), [0x67, 0x11, 0, 0, 0, 0]);
+    ecu.handle(&[0x10, 0x01]);
+    assert_eq!(ecu.unlocked(), None);
+    assert_eq!(ecu.handle(&[0x3E, 0x00]), [0x7E, 0x00]);
+    assert_eq!(ecu.handle(&[0x22, 0xF1, 0x90]), [0x7F, 0x22, 0x11]);
+}
+
+#[test]
+fn suppressed_positive_responses() {
+    let (mut ecu, _) = simulator(SimulatorConfig::default());
+    assert!(ecu.handle(&[0x3E, 0x80]).is_empty());
+    assert_eq!(ecu.handle(&[0x3E, 0x81]), [0x7F, 0x3E, 0x13]);
+
+    // The session still changes, and negative responses are still sent
+    assert!(ecu.handle(&[0x10, 0x83]).is_empty());
+    assert_eq!(ecu.handle(&[0x27, 0x01]).first(), Some(&0x67));
+    assert_eq!(ecu.handle(&[0x10, 0x85]), [0x7F, 0x10, 0x12]);
+}
+
+#[test]
+fn attempt_counter_and_delay_timer() {
+    let config = SimulatorConfig { max_attempts: 2, delay: Duration::from_millis(100) };
+    let (mut ecu, table) = simulator(config);
+    ecu.handle(&[0x10, 0x03]);
+
+    ecu.handle(&[0x27, 0x01]);
+    assert_eq!(ecu.handle(&[0x27, 0x02, 0x00]), [0x7F, 0x27, 0x35]);
+    ecu.handle(&[0x27, 0x01]);
+    assert_eq!(ecu.handle(&[0x27, 0x02, 0x00]), [0x7F, 0x27, 0x36]);
+    assert_eq!(ecu.handle(&[0x27, 0x01]), [0x7F, 0x27, 0x37]);
+
+    thread::sleep(Duration::from_millis(150));
+    let response = ecu.handle(&[0x27, 0x01]);
+    let key = calculator(&table, 1).calculate(&response[2..], &hex(KEY)).unwrap();
+    assert_eq!(ecu.handle(&[&[0x27, 0x02][..], &key].concat()), [0x67, 0x02]);
+}
+
+#[test]
+fn unlock_over_tcp() {
+    let (mut ecu, table) = simulator(SimulatorConfig::default());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let _ = ecu.serve_tcp(&listener, &mut |_, _| {});
+    });
+
+    let mut transport = TcpTransport::connect(&addr, Duration::from_secs(5)).unwrap();
+    let report = client::unlock(&mut transport, &calculator(&table, 1), &hex(KEY)).unwrap();
+    assert_eq!(report.seed.len(), 16);
+    assert!(report.key.is_some());
+    drop(transport);
+
+    // A new connection locks the ECU again
+    let mut transport = TcpTransport::connect(&addr, Duration::from_secs(5)).unwrap();
+    let err = client::unlock(&mut transport, &calculator(&table, 1), &hex("000102030405060708090a0b0c0d0e0f")).unwrap_err();
+    assert_eq!(err.downcast_ref::<NegativeResponse>().unwrap().nrc, 0x35);
+}
+
+#[test]
+fn tcp_message_cut_off_by_a_timeout_is_received_later() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let (next, wait) = mpsc::channel();
+    let peer = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(&[0, 0, 0, 6, 0x67, 0x01]).unwrap();
+        wait.recv().unwrap();
+        stream.write_all(&[0xaa, 0xbb, 0xcc, 0xdd, 0, 0, 0, 2, 0x67, 0x02]).unwrap();
+    });
+
+    let mut transport = TcpTransport::connect(&addr, Duration::from_secs(5)).unwrap();
+    let err = transport.receive(Duration::from_millis(100)).unwrap_err();
+    assert_eq!(err.downcast_ref::<io::Error>().unwrap().kind(), io::ErrorKind::TimedOut);
+
+    next.send(()).unwrap();
+    assert_eq!(transport.receive(Duration::from_secs(5)).unwrap(), [0x67, 0x01, 0xaa, 0xbb, 0xcc, 0xdd]);
+    assert_eq!(transport.receive(Duration::from_secs(5)).unwrap(), [0x67, 0x02]);
+    peer.join().unwrap();
+}
@@ -0,0 +1,43 @@
+// UDS SecurityAccess framing
+
+use seed2cmac::uds::{parse_hex_frame, parse_seed_response, parse_send_key_response, request_seed, send_key, NegativeResponse};
+use seed2cmac::SecurityLevel;
+
+#[test]
+fn parse_seed_response_frame() {
+    let frame = parse_hex_frame("67 11 A1 B2\tC3 d4").unwrap();
+    let response = parse_seed_response(&frame).unwrap();
+
+    assert_eq!(response.level, SecurityLevel::new(0x11).unwrap());
+    assert_eq!(response.seed, [0xA1, 0xB2, 0xC3, 0xD4]);
+}
+
+#[test]
+fn reject_invalid_seed_responses() {
+    // Wrong SID, sendKey sub-function, no seed
+    assert!(parse_seed_response(&[0x62, 0x01, 0xAA]).is_err());
+    assert!(parse_seed_response(&[0x67, 0x02, 0xAA]).is_err());
+    assert!(parse_seed_response(&[0x67, 0x01]).is_err());
+    assert!(parse_seed_response(&[]).is_err());
+    assert!(parse_hex_frame("67 0").is_err());
+}
+
+#[test]
+fn negative_response() {
+    let err = parse_seed_response(&[0x7F, 0x27, 0x37]).unwrap_err();
+    let nrc = err.downcast_ref::<NegativeResponse>().unwrap();
+
+    assert_eq!(nrc.nrc, 0x37);
+    assert_eq!(err.to_string(), "negative response to 0x27: NRC 0x37 (requiredTimeDelayNotExpired)");
+}
+
+#[test]
+fn build_requests() {
+    let level = SecurityLevel::new(0x01).unwrap();
+
+    assert_eq!(request_seed(level), [0x27, 0x01]);
+    assert_eq!(send_key(level, &[0xDE, 0xAD]), [0x27, 0x02, 0xDE, 0xAD]);
+    assert!(parse_send_key_response(&[0x67, 0x02], level).is_ok());
+    assert!(parse_send_key_response(&[0x67, 0x04], level).is_err());
+    assert!(parse_send_key_response(&[0x7F, 0x27, 0x35], level).is_err());
+}
@@ -0,0 +1,190 @@
+// Properties of the hex and XOR helpers over random inputs, flexible hex input and output formats
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use seed2cmac::util::{
+    bytes_to_hex_string, check_hex_input, hex_string_to_bytes, normalize_hex_input, parse_hex_input, xor_bytes, HexInputCheck,
+    HexInputError, OutputFormat,
+};
+use seed2cmac::{MaskTable, SecurityLevel};
+
+const CASES: usize = 500;
+
+fn rng() -> StdRng {
+    StdRng::seed_from_u64(0x5eed_2c3a)
+}
+
+fn random_bytes(rng: &mut StdRng, max_len: usize) -> Vec<u8> {
+    let len = rng.gen_range(0..=max_len);
+    (0..len).map(|_| rng.r#gen()).collect()
+}
+
+#[test]
+fn xor_is_an_involution() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let a = random_bytes(&mut rng, 64);
+        let b: Vec<u8> = (0..a.len()).map(|_| rng.r#gen()).collect();
+
+        let masked = xor_bytes(&a, &b).unwrap();
+        assert_eq!(xor_bytes(&masked, &b).unwrap(), a);
+        assert_eq!(xor_bytes(&a, &b).unwrap(), xor_bytes(&b, &a).unwrap());
+        assert!(xor_bytes(&a, &a).unwrap().iter().all(|byte| *byte == 0));
+        assert_eq!(xor_bytes(&a, &vec![0; a.len()]).unwrap(), a);
+    }
+}
+
+#[test]
+fn xor_rejects_length_mismatch() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let a = random_bytes(&mut rng, 32);
+        let b = random_bytes(&mut rng, 32);
+        assert_eq!(xor_bytes(&a, &b).is_ok(), a.len() == b.len());
+    }
+    let err = xor_bytes(&[0; 16], &[0; 15]).unwrap_err();
+    assert_eq!(err.to_string(), "Length mismatch, a: 16, b: 15");
+}
+
+#[test]
+fn hex_round_trip() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let bytes = random_bytes(&mut rng, 64);
+        if bytes.is_empty() {
+            continue;
+        }
+        let hex = bytes_to_hex_string(&bytes);
+        assert_eq!(hex.len(), bytes.len() * 2);
+        assert_eq!(hex_string_to_bytes(&hex).unwrap(), bytes);
+        assert_eq!(hex_string_to_bytes(&hex.to_uppercase()).unwrap(), bytes);
+    }
+}
+
+#[test]
+fn hex_rejects_invalid_input() {
+    assert!(hex_string_to_bytes("").is_err());
+    assert!(hex_string_to_bytes("abc").is_err());
+    assert!(hex_string_to_bytes("0g").is_err());
+    assert!(hex_string_to_bytes("+1").is_err());
+    assert!(hex_string_to_bytes("ä0").is_err());
+
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let text: String = (0..rng.gen_range(0..16)).map(|_| rng.gen_range(' '..='~')).collect();
+        let valid = !text.is_empty() && text.len().is_multiple_of(2) && text.chars().all(|c| c.is_ascii_hexdigit());
+        assert_eq!(hex_string_to_bytes(&text).is_ok(), valid, "{:?}", text);
+    }
+}
+
+/// Random text only ever fails to parse, never panics
+#[test]
+fn mask_parser_survives_random_input() {
+    let pieces = ["A", "BMS", " ", "=", "0x", "1", "11", "ff", "\n", "[", "]", "#", "algo=", "response=", "cipher=", "aes256", "é"];
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let text: String = (0..rng.gen_range(0..24)).map(|_| pieces[rng.gen_range(0..pieces.len())]).collect();
+        let _ = MaskTable::parse(&text);
+        let _ = seed2cmac::uds::parse_hex_frame(&text);
+    }
+}
+
+#[test]
+fn hex_input_forms() {
+    let expected = [0xA1, 0xB2, 0xC3, 0x04];
+    for input in [
+        "a1b2c304",
+        "A1 B2 C3 04",
+        " A1-B2-C3-04\n",
+        "A1:B2:C3:04",
+        "0xA1, 0xB2, 0xC3, 0x4",
+        "0XA1 0XB2 0XC3 0X04",
+        "0xA1B2C304",
+        "\\xa1\\xb2\\xc3\\x04",
+        "uint8_t seed[4] = { 0xA1, 0xB2, 0xC3, 0x04 };",
+        "[0xa1, 0xb2, 0xc3, 0x04]",
+        "\"a1b2c304\"",
+        "obLDBA==",
+        "base64:obLDBA",
+    ] {
+        assert_eq!(parse_hex_input(input).unwrap(), expected, "{:?}", input);
+    }
+    assert_eq!(normalize_hex_input("0xA1, 0xB2").unwrap(), "a1b2");
+
+    // Valid hex is never read as base64
+    assert_eq!(parse_hex_input("abcd").unwrap(), [0xAB, 0xCD]);
+}
+
+#[test]
+fn hex_input_errors() {
+    assert_eq!(parse_hex_input(" , ").unwrap_err(), HexInputError::Empty);
+    assert_eq!(parse_hex_input("A1 B2 C").unwrap_err(), HexInputError::OddLength(5));
+    assert_eq!(parse_hex_input("A1 B2 G3").unwrap_err(), HexInputError::InvalidChar { position: 7, character: 'G' });
+    assert_eq!(parse_hex_input("{0xA1, 0xZ2}").unwrap_err(), HexInputError::InvalidChar { position: 10, character: 'Z' });
+    assert_eq!(parse_hex_input("äa1").unwrap_err().to_string(), "invalid character 'ä' at position 1");
+
+    // A mistyped digit is reported instead of reading the input as base64
+    assert_eq!(parse_hex_input("2b7e15g6").unwrap_err(), HexInputError::InvalidChar { position: 7, character: 'g' });
+    assert_eq!(parse_hex_input("base64:ob!").unwrap_err(), HexInputError::InvalidBase64);
+}
+
+#[test]
+fn hex_input_check_while_typing() {
+    assert_eq!(check_hex_input(""), HexInputCheck::default());
+    assert_eq!(check_hex_input("A1 B2 C"), HexInputCheck { digits: 5, invalid: Vec::new() });
+    assert_eq!(check_hex_input("a1g2zz"), HexInputCheck { digits: 3, invalid: vec![(3, 'g'), (5, 'z'), (6, 'z')] });
+    assert_eq!(check_hex_input("obLDBA==").digits, 8);
+
+    assert!(check_hex_input("0xA1, 0xB2").is_complete(2));
+    assert!(!check_hex_input("a1b2").is_complete(4));
+    assert!(!check_hex_input("a1b2g").is_complete(2));
+
+    // Agrees with parsing on complete input
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let text: String = (0..rng.gen_range(0..16)).map(|_| rng.gen_range(' '..='~')).collect();
+        let check = check_hex_input(&text);
+        if let Ok(bytes) = parse_hex_input(&text) {
+            assert!(check.is_complete(bytes.len()), "{:?}", text);
+        } else {
+            assert!(!check.invalid.is_empty() || check.digits == 0 || !check.digits.is_multiple_of(2), "{:?}", text);
+        }
+    }
+}
+
+#[test]
+fn output_formats() {
+    let response = [0xA1, 0xB2, 0xC3, 0x04];
+    let level = SecurityLevel::new(1).unwrap();
+    let expected = [
+        (OutputFormat::Hex, "a1b2c304"),
+        (OutputFormat::Upper, "A1B2C304"),
+        (OutputFormat::Spaced, "A1 B2 C3 04"),
+        (OutputFormat::CList, "0xA1, 0xB2, 0xC3, 0x04"),
+        (OutputFormat::Uds, "27 02 A1 B2 C3 04"),
+        (OutputFormat::Base64, "obLDBA=="),
+    ];
+    for (format, text) in expected {
+        assert_eq!(format.format(&response, level), text, "{}", format);
+        assert_eq!(format.name().parse::<OutputFormat>().unwrap(), format);
+    }
+    assert_eq!(OutputFormat::Uds.format(&response, SecurityLevel::new(0x11).unwrap()), "27 12 A1 B2 C3 04");
+    assert_eq!("C-List".parse::<OutputFormat>().unwrap(), OutputFormat::CList);
+    assert!("json".parse::<OutputFormat>().is_err());
+
+    // Every format but the UDS request reads back as hex input, base64 without
+    // padding only with its prefix
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let bytes = random_bytes(&mut rng, 32);
+        if bytes.is_empty() {
+            continue;
+        }
+        for format in OutputFormat::ALL.into_iter().filter(|format| *format != OutputFormat::Uds) {
+            let text = format.format(&bytes, level);
+            let input = if format == OutputFormat::Base64 { format!("base64:{}", text.as_str()) } else { text.to_string() };
+            assert_eq!(parse_hex_input(&input).unwrap(), bytes, "{}", format);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+// Verification of recorded key responses
+
+mod common;
+
+use std::error::Error;
+
+use seed2cmac::crypto::{calculate_cmac, verify, CmacCipher, SeedKeyAlgorithm};
+use seed2cmac::util::SecretBytes;
+use seed2cmac::{find_matching_entries, MaskTable, SecurityLevel, SeedKeyCalculator};
+
+use common::{hex, KEY};
+
+const TABLE: &str = "\
+A 1 = 00000000000000000000000000000000
+A 3 = 0102030405060708090a0b0c0d0e0f10
+B 1 = 0102030405060708090a0b0c0d0e0f10
+C 1 = 01020304 seed=4 response=4
+";
+
+#[test]
+fn verify_full_and_truncated_cmac() {
+    let key = hex(KEY);
+    let data = hex("6bc1bee22e409f96e93d7e117393172a");
+    let cmac = calculate_cmac(&key, &data).unwrap();
+
+    assert!(verify(&key, &data, &cmac).unwrap());
+    assert!(verify(&key, &data, &cmac[..4]).unwrap());
+    assert!(!verify(&key, &data, &[]).unwrap());
+
+    let mut wrong = cmac.clone();
+    wrong[15] ^= 1;
+    assert!(!verify(&key, &data, &wrong).unwrap());
+    assert!(verify(&key[..8], &data, &cmac).is_err());
+}
+
+#[test]
+fn calculator_verify() {
+    let table = MaskTable::parse(TABLE).unwrap();
+    let level = SecurityLevel::new(1).unwrap();
+    let calculator = SeedKeyCalculator::from_table(&table, &table.ecu("C").unwrap(), level).unwrap();
+
+    let response = calculator.calculate_hex("aabbccdd", KEY).unwrap();
+    assert!(calculator.verify_hex("aabbccdd", KEY, &response).unwrap());
+    assert!(!calculator.verify_hex("aabbccde", KEY, &response).unwrap());
+    assert!(calculator.verify_hex("aabbccdd", KEY, "").is_err());
+    assert!(calculator.verify_hex("aabbccdd", KEY, "zz").is_err());
+}
+
+#[test]
+fn mismatch_suggests_entries_with_the_same_mask() {
+    let table = MaskTable::parse(TABLE).unwrap();
+    let seed = hex("00112233445566778899aabbccddeeff");
+    let key = hex(KEY);
+
+    let b = SeedKeyCalculator::from_table(&table, &table.ecu("B").unwrap(), SecurityLevel::new(1).unwrap()).unwrap();
+    let response = b.calculate(&seed, &key).unwrap();
+
+    let a = SeedKeyCalculator::from_table(&table, &table.ecu("A").unwrap(), SecurityLevel::new(1).unwrap()).unwrap();
+    assert!(!a.verify(&seed, &key, &response).unwrap());
+
+    let matches: Vec<String> = find_matching_entries(&table, &seed, &key, &response)
+        .iter()
+        .map(|entry| format!("{} {}", entry.ecu, entry.level))
+        .collect();
+    assert_eq!(matches, ["A 0x03", "B 0x01"]);
+}
+
+/// Returns the masked seed as response and accepts any recorded response
+#[derive(Debug)]
+struct AcceptAll;
+
+impl SeedKeyAlgorithm for AcceptAll {
+    fn name(&self) -> &'static str {
+        "accept-all"
+    }
+
+    fn key_lengths(&self, _cipher: Option<CmacCipher>) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn output_len(&self, seed_len: usize) -> Result<usize, String> {
+        Ok(seed_len)
+    }
+
+    fn calculate(&self, masked_seed: &[u8], _key: &[u8], _cipher: Option<CmacCipher>) -> Result<SecretBytes, Box<dyn Error>> {
+        Ok(SecretBytes::from(masked_seed))
+    }
+
+    fn verify(&self, _masked_seed: &[u8], _key: &[u8], _cipher: Option<CmacCipher>, _response: &[u8]) -> bool {
+        true
+    }
+}
+
+#[test]
+fn calculator_verifies_through_the_algorithm() {
+    let table = MaskTable::parse(TABLE).unwrap();
+    let level = SecurityLevel::new(1).unwrap();
+    let seed = hex("aabbccdd");
+    let key = hex(KEY);
+
+    // The default verification compares with the calculated response
+    let calculator = SeedKeyCalculator::from_table(&table, &table.ecu("C").unwrap(), level).unwrap();
+    assert!(calculator.algorithm().verify(&hex("ab"), &key, None, &calculate_cmac(&key, &hex("ab")).unwrap()[..4]));
+    assert!(!calculator.algorithm().verify(&hex("ab"), &key, None, &[]));
+
+    let custom = calculator.with_algorithm(&AcceptAll);
+    assert!(custom.verify(&seed, &key, &[0; 4]).unwrap());
+    // The response length of the entry is still enforced
+    assert!(!custom.verify(&seed, &key, &[0; 5]).unwrap());
+}